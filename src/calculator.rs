@@ -0,0 +1,3300 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+enum Token {
+    Number(f64),
+    Op(char),
+    LParen,
+    RParen,
+}
+
+// Estado de la expresión que se guarda para deshacer/rehacer; incluye el
+// historial para que deshacer un `=` retire también su entrada
+#[derive(Clone, PartialEq)]
+struct EditState {
+    current_number: String,
+    tokens: Vec<Token>,
+    paren_depth: usize,
+    memory: f64,
+    history: Vec<HistoryEntry>,
+    grand_total: f64,
+    running_total: f64,
+    data_points: Vec<f64>,
+    replace_entry: bool,
+}
+
+const MAX_UNDO: usize = 50;
+const MAX_HISTORY: usize = 100;
+const MAX_TAPE: usize = 1000;
+
+// Dos AC seguidos dentro de este margen borran también memoria e historial
+const DOUBLE_AC_MS: f64 = 500.0;
+
+// Milisegundos desde la época, como `Date.now()` en el navegador
+fn now_millis() -> f64 {
+    #[cfg(target_arch = "wasm32")]
+    {
+        js_sys::Date::now()
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_millis() as f64)
+            .unwrap_or(0.0)
+    }
+}
+
+// Número aleatorio en [0, 1), como `Math.random()`
+fn random_unit() -> f64 {
+    #[cfg(target_arch = "wasm32")]
+    {
+        js_sys::Math::random()
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|elapsed| elapsed.subsec_nanos() as f64 / 1e9)
+            .unwrap_or(0.0)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum AngleMode {
+    Degrees,
+    Radians,
+}
+
+// Cómo se redondean los resultados a `precision` decimales
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum RoundingMode {
+    HalfUp,
+    HalfEven,
+    Floor,
+    Ceil,
+    Truncate,
+}
+
+// Base en la que el modo entero muestra el valor de la pantalla
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NumberBase {
+    Decimal,
+    Hexadecimal,
+    Binary,
+}
+
+#[derive(Clone)]
+pub struct Calculator {
+    display: String,
+    // Operandos y operadores ya introducidos, para la línea superior
+    expression: String,
+    current_number: String,
+    tokens: Vec<Token>,
+    paren_depth: usize,
+    history: Vec<HistoryEntry>,
+    max_history: usize,
+    memory: f64,
+    // Exponentes a partir de los cuales la pantalla usa notación científica
+    max_exponent: i32,
+    min_exponent: i32,
+    precision: usize,
+    rounding_mode: RoundingMode,
+    // Límite opcional para los resultados, pensado para clase
+    max_magnitude: Option<f64>,
+    integer_mode: bool,
+    base: NumberBase,
+    data_points: Vec<f64>,
+    // Longitud máxima del número que se está escribiendo
+    max_input_len: usize,
+    // La pantalla muestra el valor como fracción (`frac`)
+    show_fraction: bool,
+    // Proporción simplificada que muestra la pantalla tras `ratio`, hasta la siguiente tecla
+    shown_ratio: Option<String>,
+    // La pantalla muestra el ángulo en grados, minutos y segundos (`dms`)
+    show_dms: bool,
+    // Componentes ya tecleados con ° ′ ″ y posición del siguiente
+    dms_entry: Option<([f64; 3], usize)>,
+    input_limited: bool,
+    // Tras un error se vuelve a empezar en lugar de dejar la entrada a medias
+    auto_clear_on_error: bool,
+    // Separador decimal de la pantalla: '.' o ','
+    decimal_separator: char,
+    // Modo moneda: los resultados se ven con dos decimales y el símbolo delante
+    currency_mode: bool,
+    currency_symbol: String,
+    // Porcentaje que suman y quitan `tax+` y `tax-`
+    tax_rate: f64,
+    // Porcentaje de `discount`, `markup` y `original_price`
+    discount_rate: f64,
+    angle_mode: AngleMode,
+    // Suma de todos los resultados de `=`
+    grand_total: f64,
+    // Cuántas veces se ha pulsado `=` con éxito; se conserva entre recargas
+    operations_performed: usize,
+    // Total del modo rápido: cada número introducido se le suma con `total_add`
+    running_total: f64,
+    last_ac: Option<f64>,
+    just_evaluated: bool,
+    // En pantalla hay un resultado que no viene de `=` (√, π, MR…): la siguiente
+    // cifra empieza un número nuevo, pero `=` no repite la última operación
+    replace_entry: bool,
+    // Operador y operando del último `=`, para repetirlos
+    last_operation: Option<(char, f64)>,
+    // Operación fijada con `k`: cada `número =` la vuelve a aplicar
+    constant: Option<(char, f64)>,
+    // Resultado del último `=`, disponible con ANS
+    last_result: Option<f64>,
+    // Valor sin redondear del último resultado, si el redondeo lo cambió
+    unrounded: Option<f64>,
+    // Reloj inyectable para poder fijar los tiempos en los tests
+    clock: fn() -> f64,
+    // Fuente aleatoria inyectable por el mismo motivo
+    random: fn() -> f64,
+    random_range: (f64, f64),
+    undo_stack: Vec<EditState>,
+    redo_stack: Vec<EditState>,
+    // Rollo de papel: cada número y operador tecleado y cada resultado, en orden
+    tape: Vec<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub expression: String,
+    pub result: f64,
+    // Milisegundos desde la época; las sesiones importadas pueden no traerlo
+    #[serde(default)]
+    pub timestamp: Option<f64>,
+    // Expresión estructurada para poder re-ejecutarla; vacía en las operaciones unarias
+    #[serde(default)]
+    tokens: Vec<Token>,
+}
+
+// Qué copia el botón de cada entrada del historial
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum CopyMode {
+    ResultOnly,
+    FullExpression,
+}
+
+impl std::fmt::Display for HistoryEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} = {}", self.expression, self.result)
+    }
+}
+
+impl HistoryEntry {
+    pub fn copy_text(&self, mode: CopyMode) -> String {
+        match mode {
+            CopyMode::ResultOnly => self.result.to_string(),
+            CopyMode::FullExpression => self.to_string(),
+        }
+    }
+
+    // Las entradas con expresión estructurada se reconstruyen con precedencias;
+    // las unarias solo conservan el texto, del que se reconocen √, ² y 1/x
+    pub fn to_latex(&self) -> String {
+        let expression = match latex_tokens(&self.tokens) {
+            Some(latex) => latex,
+            None => latex_label(&self.expression),
+        };
+        format!("{} = {}", expression, self.result)
+    }
+}
+
+// Vista de solo lectura del estado, para los tests y para herramientas externas
+#[cfg_attr(not(test), allow(dead_code))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct CalculatorSnapshot {
+    pub display: String,
+    pub current_number: String,
+    pub operation: Option<char>,
+    pub previous_number: Option<f64>,
+    pub history_len: usize,
+}
+
+// Parte del estado que se conserva entre recargas
+#[derive(Serialize, Deserialize)]
+pub struct CalculatorState {
+    history: Vec<HistoryEntry>,
+    memory: f64,
+    // Los estados guardados antes de exportar sesiones no traen pantalla
+    #[serde(default)]
+    display: String,
+    #[serde(default)]
+    operations_performed: usize,
+}
+
+// Busca la fracción de menor denominador (hasta 1000) que reproduce el valor;
+// los enteros y los valores sin fracción sencilla devuelven `None`
+fn to_fraction(value: f64) -> Option<(i64, i64)> {
+    if !value.is_finite() || value.fract() == 0.0 {
+        return None;
+    }
+    (2..=1000).find_map(|denominator| {
+        let numerator = (value * denominator as f64).round();
+        if (numerator / denominator as f64 - value).abs() < 1e-9 {
+            Some((numerator as i64, denominator))
+        } else {
+            None
+        }
+    })
+}
+
+// Grados decimales a grados, minutos y segundos; el signo va en los grados.
+// Se trabaja en diezmilésimas de segundo para que 59,99999… no quede sin acarrear.
+fn to_dms(deg: f64) -> (i64, i64, f64) {
+    let total = (deg.abs() * 3600.0 * 1e4).round() / 1e4;
+    let degrees = (total / 3600.0).floor();
+    let minutes = ((total - degrees * 3600.0) / 60.0).floor();
+    let seconds = total - degrees * 3600.0 - minutes * 60.0;
+    (deg.signum() as i64 * degrees as i64, minutes as i64, seconds)
+}
+
+fn from_dms(degrees: f64, minutes: f64, seconds: f64) -> f64 {
+    let magnitude = degrees.abs() + minutes / 60.0 + seconds / 3600.0;
+    if degrees < 0.0 {
+        -magnitude
+    } else {
+        magnitude
+    }
+}
+
+fn format_dms(deg: f64) -> String {
+    let (degrees, minutes, seconds) = to_dms(deg);
+    let sign = if deg < 0.0 && degrees == 0 { "-" } else { "" };
+    // Los segundos se rellenan por la parte entera: `05.5`, no `5.5`
+    let seconds = ((seconds * 1e4).round() / 1e4).to_string();
+    let padding = if seconds.find('.').unwrap_or(seconds.len()) < 2 { "0" } else { "" };
+    format!("{}{}°{:02}'{}{}\"", sign, degrees, minutes, padding, seconds)
+}
+
+fn ratio_label(previous: f64, current: f64) -> String {
+    format!("{}:{} → {}", previous, current, simplify_ratio(previous, current))
+}
+
+// `4:2` se simplifica a `2:1`; si no son enteros se refiere a 1
+fn simplify_ratio(previous: f64, current: f64) -> String {
+    match (to_integer(previous), to_integer(current)) {
+        (Some(a), Some(b)) if b != 0 => {
+            // En i128 `i64::MIN` tiene valor absoluto y la división no desborda
+            let (a, b) = (i128::from(a), i128::from(b));
+            let divisor = gcd(a, b).max(1);
+            format!("{}:{}", a / divisor, b / divisor)
+        }
+        _ => format!("{}:1", previous / current),
+    }
+}
+
+fn gcd(a: i128, b: i128) -> i128 {
+    if b == 0 {
+        a.abs()
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+// Precedencia de un fragmento LaTeX ya generado; los átomos no necesitan paréntesis
+const LATEX_ATOM: u8 = u8::MAX;
+
+fn latex_number(number: f64) -> (String, u8) {
+    if number < 0.0 {
+        (number.to_string(), precedence('-'))
+    } else {
+        (number.to_string(), LATEX_ATOM)
+    }
+}
+
+fn latex_group(latex: String, wrap: bool) -> String {
+    if wrap {
+        format!("\\left({}\\right)", latex)
+    } else {
+        latex
+    }
+}
+
+fn latex_binary(
+    op: char,
+    (left, left_prec): (String, u8),
+    (right, right_prec): (String, u8),
+) -> (String, u8) {
+    let prec = precedence(op);
+    match op {
+        '/' => (format!("\\frac{{{}}}{{{}}}", left, right), LATEX_ATOM),
+        '^' => (
+            format!("{}^{{{}}}", latex_group(left, left_prec != LATEX_ATOM), right),
+            prec,
+        ),
+        _ => {
+            let symbol = match op {
+                '*' => "\\cdot",
+                '%' => "\\bmod",
+                '&' => "\\land",
+                '|' => "\\lor",
+                'x' => "\\oplus",
+                '+' => "+",
+                _ => "-",
+            };
+            // La resta y el módulo no son asociativos: `a - (b - c)` conserva el paréntesis
+            let wrap_right = right_prec < prec || (right_prec == prec && matches!(op, '-' | '%'));
+            (
+                format!(
+                    "{} {} {}",
+                    latex_group(left, left_prec < prec),
+                    symbol,
+                    latex_group(right, wrap_right)
+                ),
+                prec,
+            )
+        }
+    }
+}
+
+// Convierte la expresión a LaTeX con el mismo shunting-yard que la evaluación
+fn latex_tokens(tokens: &[Token]) -> Option<String> {
+    fn reduce(output: &mut Vec<(String, u8)>, op: char) -> Option<()> {
+        let right = output.pop()?;
+        let left = output.pop()?;
+        output.push(latex_binary(op, left, right));
+        Some(())
+    }
+
+    let mut output: Vec<(String, u8)> = Vec::new();
+    let mut operators: Vec<Token> = Vec::new();
+    for &token in tokens {
+        match token {
+            Token::Number(number) => output.push(latex_number(number)),
+            Token::Op(op) => {
+                while let Some(&Token::Op(top)) = operators.last() {
+                    if precedence(top) < precedence(op)
+                        || (precedence(top) == precedence(op) && is_right_associative(op))
+                    {
+                        break;
+                    }
+                    operators.pop();
+                    reduce(&mut output, top)?;
+                }
+                operators.push(token);
+            }
+            Token::LParen => operators.push(token),
+            Token::RParen => loop {
+                match operators.pop()? {
+                    Token::LParen => break,
+                    Token::Op(op) => reduce(&mut output, op)?,
+                    _ => return None,
+                }
+            },
+        }
+    }
+    while let Some(token) = operators.pop() {
+        let Token::Op(op) = token else {
+            return None;
+        };
+        reduce(&mut output, op)?;
+    }
+    let (latex, _) = output.pop()?;
+    output.is_empty().then_some(latex)
+}
+
+fn latex_label(label: &str) -> String {
+    if let Some(radicand) = label.strip_prefix('√') {
+        format!("\\sqrt{{{}}}", radicand)
+    } else if let Some(base) = label.strip_suffix('²') {
+        format!("{}^{{2}}", base)
+    } else if let Some(denominator) = label.strip_prefix("1/") {
+        format!("\\frac{{1}}{{{}}}", denominator)
+    } else {
+        format!("\\mathrm{{{}}}", label)
+    }
+}
+
+// Entrecomilla el campo si contiene separadores, duplicando las comillas internas
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+// Los operadores de bits quedan por debajo de los aritméticos, como en C
+fn precedence(op: char) -> u8 {
+    match op {
+        '^' => 6,
+        '*' | '/' | '%' => 5,
+        '+' | '-' => 4,
+        '&' => 3,
+        'x' => 2,
+        _ => 1,
+    }
+}
+
+// `2 ^ 3 ^ 2` se evalúa como `2 ^ (3 ^ 2)`
+fn is_right_associative(op: char) -> bool {
+    op == '^'
+}
+
+// `%` es el módulo internamente; en pantalla se muestra como `mod`
+fn op_label(op: char) -> String {
+    match op {
+        '%' => "mod".to_string(),
+        '&' => "and".to_string(),
+        '|' => "or".to_string(),
+        'x' => "xor".to_string(),
+        _ => op.to_string(),
+    }
+}
+
+fn format_tokens(tokens: &[Token]) -> String {
+    format_tokens_with(tokens, |number| number.to_string())
+}
+
+fn format_tokens_with(tokens: &[Token], format_number: impl Fn(f64) -> String) -> String {
+    let mut text = String::new();
+    for token in tokens {
+        match token {
+            Token::Number(number) => text.push_str(&format_number(*number)),
+            Token::Op(op) => {
+                text.push(' ');
+                text.push_str(&op_label(*op));
+                text.push(' ');
+            }
+            Token::LParen => text.push('('),
+            Token::RParen => text.push(')'),
+        }
+    }
+    text
+}
+
+// Inserta separadores de miles en la parte entera, conservando el signo y
+// la parte decimal tal cual se está escribiendo (`1234.` se ve `1,234.`)
+fn format_display(value: &str) -> String {
+    let (sign, unsigned) = match value.strip_prefix('-') {
+        Some(rest) => ("-", rest),
+        None => ("", value),
+    };
+    let split = unsigned.find(['.', 'e']).unwrap_or(unsigned.len());
+    let (integer, rest) = unsigned.split_at(split);
+    if integer.is_empty() || !integer.chars().all(|c| c.is_ascii_digit()) {
+        return value.to_string();
+    }
+
+    let mut grouped = String::new();
+    for (index, digit) in integer.chars().enumerate() {
+        if index > 0 && (integer.len() - index) % 3 == 0 {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    format!("{}{}{}", sign, grouped, rest)
+}
+
+// Redondea a `precision` decimales (0.1 + 0.2 queda en 0.3); `to_string`
+// ya omite los ceros finales
+fn round_to(value: f64, precision: usize, mode: RoundingMode) -> f64 {
+    let factor = 10f64.powi(precision as i32);
+    let scaled = value * factor;
+    // Por encima de 2^53 un f64 ya no tiene decimales que redondear
+    if !scaled.is_finite() || scaled.abs() >= 9_007_199_254_740_992.0 {
+        return value;
+    }
+    let rounded = match mode {
+        RoundingMode::HalfUp => scaled.round(),
+        RoundingMode::HalfEven => scaled.round_ties_even(),
+        RoundingMode::Floor => scaled.floor(),
+        RoundingMode::Ceil => scaled.ceil(),
+        RoundingMode::Truncate => scaled.trunc(),
+    } / factor;
+    // Evita mostrar `-0` al redondear negativos muy pequeños
+    if rounded == 0.0 {
+        0.0
+    } else {
+        rounded
+    }
+}
+
+fn logarithm(value: f64, function: fn(f64) -> f64) -> Result<f64, String> {
+    if value <= 0.0 {
+        return Err("Logaritmo de no-positivo".to_string());
+    }
+    Ok(function(value))
+}
+
+// 170! es el mayor factorial que cabe en un f64
+fn factorial(value: f64) -> Result<f64, String> {
+    if value < 0.0 || value.fract() != 0.0 {
+        return Err("Factorial requiere entero ≥ 0".to_string());
+    }
+    if value > 170.0 {
+        return Err("Desbordamiento".to_string());
+    }
+    Ok((1..=value as u64).map(|n| n as f64).product())
+}
+
+fn parse_number(value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(number) => Ok(number),
+        Err(_) => Err("Número inválido".to_string()),
+    }
+}
+
+fn apply_operation(op: char, prev: f64, current: f64) -> Result<f64, String> {
+    match op {
+        '+' => Ok(prev + current),
+        '-' => Ok(prev - current),
+        '*' => Ok(prev * current),
+        '/' => {
+            if current == 0.0 {
+                return Err("División por cero".to_string());
+            }
+            Ok(prev / current)
+        }
+        // Módulo euclídeo: el resultado nunca es negativo (`-17 mod 5 = 3`)
+        '%' => {
+            if current == 0.0 {
+                return Err("Módulo por cero".to_string());
+            }
+            Ok(prev.rem_euclid(current))
+        }
+        '^' => {
+            if prev == 0.0 && current == 0.0 {
+                return Ok(1.0);
+            }
+            if prev < 0.0 && current.fract() != 0.0 {
+                return Err("Resultado complejo".to_string());
+            }
+            let result = prev.powf(current);
+            if result.is_infinite() {
+                return Err("Desbordamiento".to_string());
+            }
+            Ok(result)
+        }
+        '&' | '|' | 'x' => {
+            let (Some(prev), Some(current)) = (to_integer(prev), to_integer(current)) else {
+                return Err("Se necesitan números enteros".to_string());
+            };
+            let result = match op {
+                '&' => prev & current,
+                '|' => prev | current,
+                _ => prev ^ current,
+            };
+            Ok(result as f64)
+        }
+        _ => Err("Operación inválida".to_string()),
+    }
+}
+
+fn to_integer(value: f64) -> Option<i64> {
+    if value.fract() == 0.0 && value.abs() <= i64::MAX as f64 {
+        Some(value as i64)
+    } else {
+        None
+    }
+}
+
+// Convierte una expresión libre como `12*(3+4)` en tokens
+fn tokenize(expr: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut number = String::new();
+    for c in expr.chars() {
+        let expects_number = matches!(tokens.last(), None | Some(Token::Op(_) | Token::LParen));
+        if c.is_ascii_digit() || c == '.' || (c == '-' && number.is_empty() && expects_number) {
+            number.push(c);
+            continue;
+        }
+        if !number.is_empty() {
+            tokens.push(Token::Number(parse_number(&number)?));
+            number.clear();
+        }
+        match c {
+            '+' | '-' | '*' | '/' | '^' => tokens.push(Token::Op(c)),
+            '×' => tokens.push(Token::Op('*')),
+            '÷' => tokens.push(Token::Op('/')),
+            '(' => tokens.push(Token::LParen),
+            ')' => tokens.push(Token::RParen),
+            c if c.is_whitespace() => {}
+            _ => return Err(format!("Carácter no válido: '{}'", c)),
+        }
+    }
+    if !number.is_empty() {
+        tokens.push(Token::Number(parse_number(&number)?));
+    }
+    Ok(tokens)
+}
+
+// Resuelve las operaciones pendientes al pulsar un operador, como una
+// calculadora física: tras `3 + 4 +` se ve `7 +`. Solo se reduce lo que la
+// precedencia permite (`2 + 3 *` se queda igual) y nunca a través de un
+// paréntesis. Los tokens originales no cambian para que el historial guarde
+// la expresión completa.
+fn reduce_pending(tokens: &[Token]) -> Vec<Token> {
+    let mut reduced: Vec<Token> = Vec::new();
+    for &token in tokens {
+        if let Token::Op(op) = token {
+            while let [.., Token::Number(prev), Token::Op(pending), Token::Number(current)] = reduced[..] {
+                let reducible = precedence(pending) > precedence(op)
+                    || (precedence(pending) == precedence(op) && !is_right_associative(op));
+                if !reducible {
+                    break;
+                }
+                // Errores como la división por cero se dejan para `=`
+                let Ok(result) = apply_operation(pending, prev, current) else {
+                    break;
+                };
+                reduced.truncate(reduced.len() - 3);
+                reduced.push(Token::Number(result));
+            }
+        }
+        reduced.push(token);
+    }
+    reduced
+}
+
+// Lo que paga cada uno al repartir la cuenta con la propina incluida
+pub fn tip_split(bill: f64, tip_pct: f64, people: usize) -> Result<f64, String> {
+    if people == 0 {
+        return Err("Tiene que haber al menos una persona".to_string());
+    }
+    Ok(bill * (1.0 + tip_pct / 100.0) / people as f64)
+}
+
+// Valor que debe verse mientras se escribe el siguiente operando: el último
+// resultado parcial o, tras cerrar un paréntesis, el valor del grupo
+fn running_value(tokens: &[Token]) -> Option<f64> {
+    if let [.., Token::RParen] = tokens {
+        let mut depth = 0;
+        for (index, token) in tokens.iter().enumerate().rev() {
+            match token {
+                Token::RParen => depth += 1,
+                Token::LParen => depth -= 1,
+                _ => {}
+            }
+            if depth == 0 {
+                return evaluate_tokens(&tokens[index..]).ok();
+            }
+        }
+    }
+    reduce_pending(tokens).iter().rev().find_map(|token| match token {
+        Token::Number(number) => Some(*number),
+        _ => None,
+    })
+}
+
+// Evalúa la expresión respetando la precedencia (algoritmo shunting-yard)
+fn evaluate_tokens(tokens: &[Token]) -> Result<f64, String> {
+    let mut expect_number = true;
+    let mut depth = 0;
+    for (index, token) in tokens.iter().enumerate() {
+        match (token, expect_number) {
+            (Token::Number(_), true) => expect_number = false,
+            (Token::Op(_), false) => expect_number = true,
+            (Token::Op(op), true) if index == 0 => {
+                return Err(format!("Falta un número antes de '{}'", op));
+            }
+            (Token::Op(_), true) => return Err("Operadores consecutivos".to_string()),
+            (Token::Number(_) | Token::LParen, false) => {
+                return Err("Falta un operador".to_string());
+            }
+            (Token::LParen, true) => depth += 1,
+            (Token::RParen, true) => return Err("Expresión incompleta".to_string()),
+            (Token::RParen, false) => {
+                if depth == 0 {
+                    return Err("Paréntesis sin abrir".to_string());
+                }
+                depth -= 1;
+            }
+        }
+    }
+    if tokens.is_empty() {
+        return Err("Expresión vacía".to_string());
+    }
+    if depth > 0 {
+        return Err("Paréntesis sin cerrar".to_string());
+    }
+    if expect_number {
+        return Err("La expresión termina en un operador".to_string());
+    }
+
+    let mut output = Vec::new();
+    let mut operators: Vec<Token> = Vec::new();
+    for &token in tokens {
+        match token {
+            Token::Number(_) => output.push(token),
+            Token::Op(op) => {
+                while let Some(&Token::Op(top)) = operators.last() {
+                    if precedence(top) < precedence(op)
+                        || (precedence(top) == precedence(op) && is_right_associative(op))
+                    {
+                        break;
+                    }
+                    output.push(Token::Op(top));
+                    operators.pop();
+                }
+                operators.push(token);
+            }
+            Token::LParen => operators.push(token),
+            Token::RParen => {
+                while let Some(top) = operators.pop() {
+                    if top == Token::LParen {
+                        break;
+                    }
+                    output.push(top);
+                }
+            }
+        }
+    }
+    output.extend(operators.into_iter().rev());
+
+    let mut stack: Vec<f64> = Vec::new();
+    for token in output {
+        match token {
+            Token::Number(number) => stack.push(number),
+            Token::Op(op) => {
+                let current = stack.pop().ok_or("Expresión incompleta")?;
+                let prev = stack.pop().ok_or("Expresión incompleta")?;
+                stack.push(apply_operation(op, prev, current)?);
+            }
+            Token::LParen | Token::RParen => {}
+        }
+    }
+    stack.pop().ok_or_else(|| "Expresión vacía".to_string())
+}
+
+impl Calculator {
+    pub fn new() -> Self {
+        Calculator {
+            display: String::from("0"),
+            expression: String::new(),
+            current_number: String::new(),
+            tokens: Vec::new(),
+            paren_depth: 0,
+            history: Vec::new(),
+            max_history: MAX_HISTORY,
+            memory: 0.0,
+            max_exponent: 15,
+            min_exponent: -6,
+            precision: 10,
+            rounding_mode: RoundingMode::HalfUp,
+            max_magnitude: None,
+            integer_mode: false,
+            base: NumberBase::Decimal,
+            data_points: Vec::new(),
+            max_input_len: 20,
+            show_fraction: false,
+            shown_ratio: None,
+            show_dms: false,
+            dms_entry: None,
+            input_limited: false,
+            auto_clear_on_error: false,
+            decimal_separator: '.',
+            currency_mode: false,
+            currency_symbol: String::from("$"),
+            tax_rate: 16.0,
+            discount_rate: 10.0,
+            angle_mode: AngleMode::Degrees,
+            grand_total: 0.0,
+            operations_performed: 0,
+            running_total: 0.0,
+            last_ac: None,
+            just_evaluated: false,
+            replace_entry: false,
+            last_operation: None,
+            constant: None,
+            last_result: None,
+            unrounded: None,
+            clock: now_millis,
+            random: random_unit,
+            random_range: (0.0, 1.0),
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+            tape: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, value: &str) -> Result<(), String> {
+        // Internamente el separador decimal siempre es el punto
+        let value = if value == "," { "." } else { value };
+        self.input_limited = false;
+        self.unrounded = None;
+        self.shown_ratio = None;
+        // Solo dos AC seguidos, sin otra tecla entre medias, borran también la memoria
+        if value != "ac" {
+            self.last_ac = None;
+        }
+        match value {
+            "undo" => self.undo_operation(),
+            "redo" => self.redo(),
+            _ => {
+                let before = self.edit_state();
+                let typed = self.current_number.clone();
+                let tape_len = self.tape.len();
+                if let Err(msg) = self.apply(value) {
+                    if self.auto_clear_on_error {
+                        self.reset();
+                        self.update_display();
+                    }
+                    return Err(msg);
+                }
+                self.print_key(value, &typed, tape_len);
+                let mut after = self.edit_state();
+                // Que la siguiente cifra empiece otro número no es algo que deshacer
+                after.replace_entry = before.replace_entry;
+                if after != before {
+                    self.undo_stack.push(before);
+                    if self.undo_stack.len() > MAX_UNDO {
+                        self.undo_stack.remove(0);
+                    }
+                    self.redo_stack.clear();
+                }
+            }
+        }
+
+        self.update_display();
+        Ok(())
+    }
+
+    // Igual que llamar a `push` con cada entrada, parando en el primer error
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn push_all(&mut self, inputs: &[&str]) -> Result<(), String> {
+        for input in inputs {
+            self.push(input)?;
+        }
+        Ok(())
+    }
+
+    fn apply(&mut self, value: &str) -> Result<(), String> {
+        // Tras `=` un dígito empieza un número nuevo; un operador encadena el resultado
+        let starts_fresh = matches!(value, "." | "(" | "ans") || value.bytes().all(|b| b.is_ascii_digit());
+        // Las teclas que no tocan el número en pantalla no cambian qué hará la
+        // siguiente cifra: tras `= m+` un 7 sigue empezando un número nuevo
+        let keeps_entry = matches!(
+            value,
+            "m+" | "m-" | "mc" | "frac" | "dms" | "angle" | "hex" | "bin" | "dec" | "exp_input" | "swap"
+                | "gt_clear" | "clear_history" | "tape_clear" | "data_clear" | "total_reset"
+        );
+        let (just_evaluated, replace_entry) = if keeps_entry {
+            (self.just_evaluated, self.replace_entry)
+        } else {
+            (std::mem::take(&mut self.just_evaluated), std::mem::take(&mut self.replace_entry))
+        };
+        let show_fraction = std::mem::take(&mut self.show_fraction);
+        let show_dms = std::mem::take(&mut self.show_dms);
+        if (just_evaluated || replace_entry) && starts_fresh {
+            self.current_number.clear();
+        }
+        // Cualquier tecla que no siga escribiendo el ángulo lo da por terminado
+        let editing = value.bytes().all(|b| b.is_ascii_digit()) || matches!(value, "." | "<");
+        if !editing && !matches!(value, "dms_deg" | "dms_min" | "dms_sec") {
+            self.finish_dms_entry()?;
+        }
+
+        match value {
+            // Justo después de EE el menos es el signo del exponente: `1.5e-3`
+            "-" if self.current_number.ends_with('e') => self.current_number.push('-'),
+            "+" | "-" | "*" | "/" | "^" => self.push_operator(value.chars().next().unwrap())?,
+            "mod" => self.push_operator('%')?,
+            "hex" | "bin" | "dec" => {
+                if !self.integer_mode {
+                    return Err("Solo disponible en modo entero".to_string());
+                }
+                self.base = match value {
+                    "hex" => NumberBase::Hexadecimal,
+                    "bin" => NumberBase::Binary,
+                    _ => NumberBase::Decimal,
+                };
+            }
+            "and" | "or" | "xor" => {
+                if !self.integer_mode {
+                    return Err("Solo disponible en modo entero".to_string());
+                }
+                let op = match value {
+                    "and" => '&',
+                    "or" => '|',
+                    _ => 'x',
+                };
+                self.push_operator(op)?
+            }
+            "(" => {
+                self.flush_number()?;
+                self.tokens.push(Token::LParen);
+                self.paren_depth += 1;
+            }
+            ")" => {
+                if self.paren_depth == 0 {
+                    return Err("Paréntesis sin abrir".to_string());
+                }
+                self.flush_number()?;
+                self.tokens.push(Token::RParen);
+                self.paren_depth -= 1;
+            }
+            "=" => {
+                if self.paren_depth > 0 {
+                    return Err("Paréntesis sin cerrar".to_string());
+                }
+                if just_evaluated && self.tokens.is_empty() {
+                    self.repeat_last_operation()?;
+                } else if let (Some((op, operand)), true) = (self.constant, self.tokens.is_empty()) {
+                    self.last_operation = Some((op, operand));
+                    self.operate_on_entry(op, operand)?;
+                } else if !self.tokens.is_empty() || self.current_number.is_empty() {
+                    // Un número suelto sin operación pendiente no genera historial
+                    let tokens = self.expression_tokens()?;
+                    let result = self.round_result(evaluate_tokens(&tokens)?)?;
+                    self.last_operation = match tokens[..] {
+                        [.., Token::Op(op), Token::Number(operand)] => Some((op, operand)),
+                        _ => None,
+                    };
+                    self.finish_evaluation(tokens.to_vec(), result);
+                }
+            }
+            "." if self.integer_mode => {}
+            "." => {
+                if self.current_number.is_empty() {
+                    self.current_number.push_str("0.");
+                } else if !self.current_number.contains(['.', 'e']) {
+                    self.current_number.push('.');
+                }
+            }
+            // Los dígitos siguientes forman el exponente: `1.5` EE `3` es 1500
+            // Un resultado no se convierte en mantisa: EE solo sigue un número tecleado
+            "exp_input" if just_evaluated || replace_entry => {}
+            "exp_input" => {
+                if self.current_number.ends_with(|c: char| c.is_ascii_digit())
+                    && !self.current_number.contains('e')
+                {
+                    self.current_number.push('e');
+                }
+            }
+            // Sin un resultado previo ANS vale 0
+            "ans" => self.insert_operand(self.last_result.unwrap_or(0.0))?,
+            "%" => self.percent()?,
+            // Un resultado con el signo cambiado sigue siendo un resultado
+            "neg" => {
+                self.toggle_sign();
+                self.replace_entry = just_evaluated || replace_entry;
+            }
+            "abs" => self.apply_unary(|value| format!("|{}|", value), |value| Ok(value.abs()))?,
+            // A diferencia de `%`, no depende de la operación pendiente
+            "pct" => self.apply_unary(|value| format!("{}%", value), |value| Ok(value / 100.0))?,
+            "sqrt" => self.apply_unary(
+                |value| format!("√{}", value),
+                |value| {
+                    if value < 0.0 {
+                        return Err("Raíz de negativo".to_string());
+                    }
+                    Ok(value.sqrt())
+                },
+            )?,
+            "tax+" => {
+                let rate = self.tax_rate;
+                self.apply_unary(
+                    |value| format!("{} +{}%", value, rate),
+                    |value| Ok(value * (1.0 + rate / 100.0)),
+                )?
+            }
+            "tax-" => {
+                let rate = self.tax_rate;
+                self.apply_unary(
+                    |value| format!("{} -{}%", value, rate),
+                    |value| Ok(value / (1.0 + rate / 100.0)),
+                )?
+            }
+            "discount" => {
+                let rate = self.discount_rate;
+                self.apply_unary(
+                    |value| format!("{} -{}% dto", value, rate),
+                    |value| Ok(value * (1.0 - rate / 100.0)),
+                )?
+            }
+            "markup" => {
+                let rate = self.discount_rate;
+                self.apply_unary(
+                    |value| format!("{} +{}% margen", value, rate),
+                    |value| Ok(value * (1.0 + rate / 100.0)),
+                )?
+            }
+            // Precio antes del descuento: deshace `discount`
+            "original_price" => {
+                let rate = self.discount_rate;
+                self.apply_unary(
+                    |value| format!("{} sin -{}% dto", value, rate),
+                    |value| {
+                        if rate == 100.0 {
+                            return Err("Con un descuento del 100% no hay precio original".to_string());
+                        }
+                        Ok(value / (1.0 - rate / 100.0))
+                    },
+                )?
+            }
+            "sqr" => self.apply_unary(|value| format!("{}²", value), |value| Ok(value * value))?,
+            "recip" => self.apply_unary(
+                |value| format!("1/{}", value),
+                |value| {
+                    if value == 0.0 {
+                        return Err("División por cero".to_string());
+                    }
+                    Ok(1.0 / value)
+                },
+            )?,
+            "sin" => self.apply_trig("sin", f64::sin)?,
+            "cos" => self.apply_trig("cos", f64::cos)?,
+            "tan" => self.apply_trig("tan", f64::tan)?,
+            "asin" => self.apply_inverse_trig("asin", f64::asin, true)?,
+            "acos" => self.apply_inverse_trig("acos", f64::acos, true)?,
+            "atan" => self.apply_inverse_trig("atan", f64::atan, false)?,
+            "log" => self.apply_unary(
+                |value| format!("log({})", value),
+                |value| logarithm(value, f64::log10),
+            )?,
+            "ln" => self.apply_unary(
+                |value| format!("ln({})", value),
+                |value| logarithm(value, f64::ln),
+            )?,
+            "exp" => self.apply_unary(|value| format!("exp({})", value), |value| Ok(value.exp()))?,
+            "fact" => self.apply_unary(|value| format!("{}!", value), factorial)?,
+            // Sustituyen al número en curso; `current_number` guarda todos los decimales
+            "pi" => self.replace_with(std::f64::consts::PI),
+            "e" => self.replace_with(std::f64::consts::E),
+            "angle" => {
+                self.angle_mode = match self.angle_mode {
+                    AngleMode::Degrees => AngleMode::Radians,
+                    AngleMode::Radians => AngleMode::Degrees,
+                }
+            }
+            // Lo que se ve en pantalla, también el resultado parcial de `3 + 4 +`
+            "m+" => self.memory += self.shown_value(),
+            "m-" => self.memory -= self.shown_value(),
+            "mr" => self.replace_with(self.memory),
+            "mc" => self.memory = 0.0,
+            "gt" => self.replace_with(round_to(self.grand_total, self.precision, self.rounding_mode)),
+            "gt_clear" => self.grand_total = 0.0,
+            "total_add" => {
+                self.running_total += self.current_value()?;
+                self.current_number.clear();
+            }
+            "total_reset" => self.running_total = 0.0,
+            "ac" => self.all_clear(),
+            // `3 + 5 k` fija la suma de 5 y deja el 3 en pantalla, listo para `=`;
+            // otra `k` la suelta. Solo vale con una operación sencilla pendiente.
+            "k" if self.constant.is_some() => {
+                self.constant = None;
+                self.just_evaluated = just_evaluated;
+                self.replace_entry = replace_entry;
+            }
+            "k" => {
+                let [Token::Number(left), Token::Op(op)] = self.tokens[..] else {
+                    return Err("No hay operación que fijar".to_string());
+                };
+                if self.current_number.is_empty() {
+                    return Err("No hay operación que fijar".to_string());
+                }
+                self.constant = Some((op, self.current_value()?));
+                self.tokens.clear();
+                self.paren_depth = 0;
+                self.replace_with(left);
+            }
+            "rand" => {
+                let (min, max) = self.random_range;
+                let value = self.round_result(min + (self.random)() * (max - min))?;
+                self.add_history(format!("rand({}, {})", min, max), Vec::new(), value);
+                self.replace_with(value);
+            }
+            // Alterna entre decimal y fracción solo si existe una fracción limpia
+            "frac" => self.show_fraction = !show_fraction && to_fraction(self.shown_value()).is_some(),
+            "dms" => self.show_dms = !show_dms,
+            "dms_deg" => self.enter_dms_component(0)?,
+            "dms_min" => self.enter_dms_component(1)?,
+            "dms_sec" => self.enter_dms_component(2)?,
+            "data" => {
+                self.data_points.push(self.current_value()?);
+                self.current_number.clear();
+            }
+            "mean" | "sum" | "count" => self.apply_statistic(value)?,
+            "data_clear" => self.data_points.clear(),
+            "swap" => self.swap_operands()?,
+            "pct_change" => self.apply_binary(
+                |previous, current| format!("Δ% {} → {}", previous, current),
+                |previous, current| {
+                    if previous == 0.0 {
+                        return Err("División por cero".to_string());
+                    }
+                    Ok((current - previous) / previous * 100.0)
+                },
+            )?,
+            // La pantalla enseña `2:1`; el valor que sigue operando es el cociente
+            "ratio" => {
+                let previous = match self.tokens[..] {
+                    [.., Token::Number(previous), Token::Op(_)] => previous,
+                    _ => 0.0,
+                };
+                let current = self.current_value()?;
+                self.apply_binary(ratio_label, |previous, current| {
+                    if current == 0.0 {
+                        return Err("División por cero".to_string());
+                    }
+                    Ok(previous / current)
+                })?;
+                self.shown_ratio = Some(simplify_ratio(previous, current));
+            }
+            // Borra solo el número en curso; la operación pendiente se mantiene
+            "ce" => self.current_number.clear(),
+            "clear_history" => self.history.clear(),
+            "tape_clear" => self.tape.clear(),
+            "<" => self.undo(),
+            // Pasado el límite las pulsaciones se ignoran
+            digit if digit.len() == 1 && digit.as_bytes()[0].is_ascii_digit() => {
+                // Un cero inicial no se queda delante de otro dígito: `0` `7` es 7
+                if matches!(self.current_number.as_str(), "0" | "-0")
+                    && value.starts_with(|c: char| c.is_ascii_digit())
+                {
+                    self.current_number.pop();
+                }
+                if self.current_number.len() + value.len() > self.max_input_len {
+                    self.input_limited = true;
+                } else {
+                    self.current_number.push_str(value);
+                }
+            }
+            _ => return Err(format!("Tecla desconocida: '{}'", value)),
+        }
+        Ok(())
+    }
+
+    // En modo entero los resultados se truncan, como en las calculadoras de programador.
+    // Un infinito o un NaN nunca llega a guardarse: `inf` no se podría volver a leer.
+    fn round_result(&mut self, value: f64) -> Result<f64, String> {
+        if value.is_nan() {
+            return Err("Resultado indefinido".to_string());
+        }
+        if value.is_infinite() {
+            return Err("Desbordamiento".to_string());
+        }
+        if self.max_magnitude.is_some_and(|max| value.abs() > max) {
+            return Err("Número demasiado grande".to_string());
+        }
+        let rounded = if self.integer_mode {
+            value.trunc()
+        } else {
+            round_to(value, self.precision, self.rounding_mode)
+        };
+        self.unrounded = (rounded != value).then_some(value);
+        Ok(rounded)
+    }
+
+    fn finish_evaluation(&mut self, tokens: Vec<Token>, result: f64) {
+        self.last_result = Some(result);
+        self.add_history(format_tokens(&tokens), tokens, result);
+        self.grand_total += result;
+        self.operations_performed += 1;
+        self.current_number = result.to_string();
+        self.tokens.clear();
+        self.just_evaluated = true;
+    }
+
+    // `2 + 3 = =` vuelve a sumar 3 al resultado, como en las calculadoras de bolsillo
+    fn repeat_last_operation(&mut self) -> Result<(), String> {
+        let Some((op, operand)) = self.last_operation else {
+            return Ok(());
+        };
+        self.operate_on_entry(op, operand)
+    }
+
+    // El número en pantalla operado con `op` y `operand`, como resultado de `=`
+    fn operate_on_entry(&mut self, op: char, operand: f64) -> Result<(), String> {
+        let tokens = [Token::Number(self.current_value()?), Token::Op(op), Token::Number(operand)];
+        let result = self.round_result(evaluate_tokens(&tokens)?)?;
+        self.finish_evaluation(tokens.to_vec(), result);
+        Ok(())
+    }
+
+    fn edit_state(&self) -> EditState {
+        EditState {
+            current_number: self.current_number.clone(),
+            tokens: self.tokens.clone(),
+            paren_depth: self.paren_depth,
+            memory: self.memory,
+            history: self.history.clone(),
+            grand_total: self.grand_total,
+            running_total: self.running_total,
+            data_points: self.data_points.clone(),
+            replace_entry: self.just_evaluated || self.replace_entry,
+        }
+    }
+
+    fn restore(&mut self, state: EditState) {
+        self.current_number = state.current_number;
+        self.tokens = state.tokens;
+        self.paren_depth = state.paren_depth;
+        self.memory = state.memory;
+        self.history = state.history;
+        self.grand_total = state.grand_total;
+        self.running_total = state.running_total;
+        self.data_points = state.data_points;
+        self.replace_entry = state.replace_entry;
+    }
+
+    // Deshace la última operación completa (`<` solo borra un carácter)
+    fn undo_operation(&mut self) {
+        if let Some(state) = self.undo_stack.pop() {
+            self.redo_stack.push(self.edit_state());
+            self.restore(state);
+            // Lo que se recupera se sigue editando, no es un resultado
+            self.just_evaluated = false;
+            self.replace_entry = false;
+        }
+    }
+
+    fn redo(&mut self) {
+        if let Some(state) = self.redo_stack.pop() {
+            self.undo_stack.push(self.edit_state());
+            self.restore(state);
+        }
+    }
+
+    // Pasa el número que se está escribiendo a la lista de tokens
+    fn flush_number(&mut self) -> Result<(), String> {
+        if !self.current_number.is_empty() {
+            let number = parse_number(&self.current_number)?;
+            self.tokens.push(Token::Number(number));
+            self.current_number.clear();
+        }
+        Ok(())
+    }
+
+    // Un operador como primera entrada opera sobre el 0 de la pantalla,
+    // igual que en una calculadora de bolsillo: `+ 5 =` da 5
+    fn push_operator(&mut self, op: char) -> Result<(), String> {
+        self.flush_number()?;
+        if self.tokens.is_empty() {
+            self.tokens.push(Token::Number(0.0));
+        }
+        self.tokens.push(Token::Op(op));
+        Ok(())
+    }
+
+    // Intercambia el operando anterior con el que se está escribiendo:
+    // `10 - 3 swap` queda como `3 - 10`
+    fn swap_operands(&mut self) -> Result<(), String> {
+        if self.current_number.is_empty() {
+            return Ok(());
+        }
+        let len = self.tokens.len();
+        if let [.., Token::Number(previous), Token::Op(_)] = self.tokens[..] {
+            self.tokens[len - 2] = Token::Number(parse_number(&self.current_number)?);
+            self.current_number = previous.to_string();
+        }
+        Ok(())
+    }
+
+    // Valor del número que se está escribiendo (0 si todavía no hay ninguno)
+    fn current_value(&self) -> Result<f64, String> {
+        if self.current_number.is_empty() {
+            Ok(0.0)
+        } else {
+            parse_number(&self.current_number)
+        }
+    }
+
+    // Tokens desde el último paréntesis abierto hasta el operador pendiente
+    fn pending_segment(&self) -> &[Token] {
+        let end = self.tokens.len().saturating_sub(1);
+        let mut depth = 0;
+        let mut start = 0;
+        for index in (0..end).rev() {
+            match self.tokens[index] {
+                Token::RParen => depth += 1,
+                Token::LParen if depth == 0 => {
+                    start = index + 1;
+                    break;
+                }
+                Token::LParen => depth -= 1,
+                _ => {}
+            }
+        }
+        &self.tokens[start..end]
+    }
+
+    // Como en las calculadoras físicas: en `50 + 10 %` el 10 pasa a ser el
+    // 10% de 50 (5). Con `*`, `/` o sin operación pendiente solo divide entre
+    // 100. Si todavía no se ha escrito ningún número no hace nada.
+    fn percent(&mut self) -> Result<(), String> {
+        if self.current_number.is_empty() {
+            return Ok(());
+        }
+        let current = parse_number(&self.current_number)?;
+        let value = match self.tokens.last() {
+            Some(Token::Op('+' | '-')) => {
+                let base = evaluate_tokens(self.pending_segment())?;
+                base * current / 100.0
+            }
+            _ => current / 100.0,
+        };
+        self.replace_with(value);
+        Ok(())
+    }
+
+    // Operaciones unarias inmediatas: no esperan a `=` y quedan en el historial
+    fn apply_unary(
+        &mut self,
+        label: impl Fn(f64) -> String,
+        operation: impl Fn(f64) -> Result<f64, String>,
+    ) -> Result<(), String> {
+        let value = self.current_value()?;
+        let result = self.round_result(operation(value)?)?;
+        self.add_history(label(value), Vec::new(), result);
+        self.replace_with(result);
+        Ok(())
+    }
+
+    // Como las unarias, pero con el operando anterior al operador pendiente;
+    // el operador solo separa los dos valores y se descarta
+    fn apply_binary(
+        &mut self,
+        label: impl Fn(f64, f64) -> String,
+        operation: impl Fn(f64, f64) -> Result<f64, String>,
+    ) -> Result<(), String> {
+        let [.., Token::Number(previous), Token::Op(_)] = self.tokens[..] else {
+            return Err("Se necesitan dos valores".to_string());
+        };
+        if self.current_number.is_empty() {
+            return Err("Se necesitan dos valores".to_string());
+        }
+        let current = self.current_value()?;
+        let result = self.round_result(operation(previous, current)?)?;
+        self.add_history(label(previous, current), Vec::new(), result);
+        self.tokens.truncate(self.tokens.len() - 2);
+        self.replace_with(result);
+        Ok(())
+    }
+
+    // Estadísticas sobre los datos introducidos con `data`
+    fn apply_statistic(&mut self, statistic: &str) -> Result<(), String> {
+        if self.data_points.is_empty() {
+            return Err("No hay datos".to_string());
+        }
+        let count = self.data_points.len() as f64;
+        let sum: f64 = self.data_points.iter().sum();
+        let result = self.round_result(match statistic {
+            "mean" => sum / count,
+            "sum" => sum,
+            _ => count,
+        })?;
+        self.add_history(format!("{}(n={})", statistic, self.data_points.len()), Vec::new(), result);
+        self.replace_with(result);
+        Ok(())
+    }
+
+    fn replace_with(&mut self, value: f64) {
+        self.current_number = value.to_string();
+        self.replace_entry = true;
+    }
+
+    // Los ángulos en DMS siempre son grados; en modo radianes se convierten
+    fn angle_to_degrees(&self, value: f64) -> f64 {
+        match self.angle_mode {
+            AngleMode::Degrees => value,
+            AngleMode::Radians => value.to_degrees(),
+        }
+    }
+
+    fn angle_from_degrees(&self, degrees: f64) -> f64 {
+        match self.angle_mode {
+            AngleMode::Degrees => degrees,
+            AngleMode::Radians => degrees.to_radians(),
+        }
+    }
+
+    // `30 ° 30 ′` va guardando cada componente; tras los segundos el ángulo está completo
+    fn enter_dms_component(&mut self, position: usize) -> Result<(), String> {
+        let (mut parts, _) = self.dms_entry.take().unwrap_or(([0.0; 3], 0));
+        parts[position] = self.current_value()?;
+        self.current_number.clear();
+        self.dms_entry = Some((parts, position + 1));
+        if position == 2 {
+            self.finish_dms_entry()?;
+        }
+        Ok(())
+    }
+
+    // Lo tecleado tras el último símbolo cuenta como el componente siguiente
+    fn finish_dms_entry(&mut self) -> Result<(), String> {
+        let Some((mut parts, next)) = self.dms_entry.take() else {
+            return Ok(());
+        };
+        if next < 3 && !self.current_number.is_empty() {
+            parts[next] = self.current_value()?;
+        }
+        let degrees = from_dms(parts[0], parts[1], parts[2]);
+        self.current_number = self.angle_from_degrees(degrees).to_string();
+        Ok(())
+    }
+
+    fn apply_trig(&mut self, name: &str, function: fn(f64) -> f64) -> Result<(), String> {
+        let mode = self.angle_mode;
+        self.apply_unary(
+            |value| format!("{}({})", name, value),
+            |value| {
+                let radians = match mode {
+                    AngleMode::Degrees => value.to_radians(),
+                    AngleMode::Radians => value,
+                };
+                let result = function(radians);
+                // tan(90°) no llega a ser infinito por el redondeo de π
+                if result.abs() > 1e15 {
+                    return Err("Desbordamiento".to_string());
+                }
+                Ok(result)
+            },
+        )
+    }
+
+    // El resultado en radianes se pasa a grados si es el modo activo;
+    // `asin` y `acos` solo están definidas en [-1, 1]
+    fn apply_inverse_trig(
+        &mut self,
+        name: &str,
+        function: fn(f64) -> f64,
+        bounded: bool,
+    ) -> Result<(), String> {
+        let mode = self.angle_mode;
+        self.apply_unary(
+            |value| format!("{}({})", name, value),
+            |value| {
+                if bounded && !(-1.0..=1.0).contains(&value) {
+                    return Err("Fuera de dominio".to_string());
+                }
+                let radians = function(value);
+                Ok(match mode {
+                    AngleMode::Degrees => radians.to_degrees(),
+                    AngleMode::Radians => radians,
+                })
+            },
+        )
+    }
+
+    // Cambia el signo del número que se está escribiendo; el cero no tiene signo
+    fn toggle_sign(&mut self) {
+        if let Some(positive) = self.current_number.strip_prefix('-') {
+            self.current_number = positive.to_string();
+        } else if parse_number(&self.current_number).is_ok_and(|number| number != 0.0) {
+            self.current_number.insert(0, '-');
+        }
+    }
+
+    fn all_clear(&mut self) {
+        let now = (self.clock)();
+        let double = self.last_ac.is_some_and(|last| now - last <= DOUBLE_AC_MS);
+        self.reset();
+        if double {
+            self.memory = 0.0;
+            self.history.clear();
+            self.last_ac = None;
+        } else {
+            self.last_ac = Some(now);
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.display = String::from("0");
+        self.current_number.clear();
+        self.tokens.clear();
+        self.paren_depth = 0;
+        self.last_operation = None;
+        self.constant = None;
+        self.dms_entry = None;
+    }
+
+    // Tokens de la expresión completa, incluyendo el número que se está escribiendo
+    fn expression_tokens(&self) -> Result<Vec<Token>, String> {
+        let mut tokens = self.tokens.clone();
+        if !self.current_number.is_empty() {
+            tokens.push(Token::Number(parse_number(&self.current_number)?));
+        }
+        Ok(tokens)
+    }
+
+    // Evalúa una expresión pegada desde fuera y deja el resultado en pantalla
+    pub fn evaluate_expression(&mut self, expr: &str) -> Result<f64, String> {
+        let tokens = tokenize(&self.delocalize(expr))?;
+        let result = self.round_result(evaluate_tokens(&tokens)?)?;
+        self.add_history(format_tokens(&tokens), tokens, result);
+
+        self.current_number = result.to_string();
+        self.tokens.clear();
+        self.paren_depth = 0;
+        self.just_evaluated = true;
+        self.last_result = Some(result);
+        self.update_display();
+        Ok(result)
+    }
+
+    // Borra el último carácter; sin número en curso retrocede sobre el último
+    // token, devolviendo a edición el operando anterior a un operador
+    pub fn undo(&mut self) {
+        if !self.current_number.is_empty() {
+            self.current_number.pop();
+            // Un signo suelto no es un número: se borra con la última cifra
+            if self.current_number == "-" {
+                self.current_number.clear();
+            }
+        } else {
+            match self.tokens.pop() {
+                Some(Token::Op(_)) => {
+                    if let Some(&Token::Number(number)) = self.tokens.last() {
+                        self.tokens.pop();
+                        self.current_number = number.to_string();
+                    }
+                }
+                Some(Token::Number(number)) => self.current_number = number.to_string(),
+                Some(Token::LParen) => self.paren_depth -= 1,
+                Some(Token::RParen) => self.paren_depth += 1,
+                None => {}
+            }
+        }
+        self.update_display();
+    }
+
+    // Muestra la expresión abierta (con paréntesis) mientras se escribe
+    fn update_display(&mut self) {
+        self.expression = format_tokens_with(&self.tokens, |number| {
+            self.format_number(&number.to_string())
+        })
+        .trim()
+        .to_string();
+        let fraction = if self.show_fraction { to_fraction(self.shown_value()) } else { None };
+        self.display = if let Some(ratio) = &self.shown_ratio {
+            self.localize(ratio)
+        } else if let Some((numerator, denominator)) = fraction {
+            format!("{}/{}", numerator, denominator)
+        } else if let (Some((parts, _)), true) = (self.dms_entry, self.current_number.is_empty()) {
+            format_dms(from_dms(parts[0], parts[1], parts[2]))
+        } else if self.show_dms {
+            format_dms(self.angle_to_degrees(self.shown_value()))
+        } else if self.base != NumberBase::Decimal {
+            self.format_in_base()
+        } else if self.currency_mode
+            && (self.just_evaluated || self.replace_entry || self.current_number.is_empty())
+        {
+            // Lo que se está escribiendo se ve tal cual; solo los resultados van en moneda
+            self.format_currency(self.shown_value())
+        } else if !self.current_number.is_empty() {
+            self.format_number(&self.current_number)
+        } else {
+            let value = running_value(&self.tokens).unwrap_or(0.0);
+            self.format_number(&value.to_string())
+        };
+    }
+
+    fn shown_value(&self) -> f64 {
+        if self.current_number.is_empty() {
+            running_value(&self.tokens).unwrap_or(0.0)
+        } else {
+            self.current_value().unwrap_or(0.0)
+        }
+    }
+
+    // Valor que muestra la pantalla en hexadecimal o binario. Los negativos se
+    // representan en complemento a dos con 64 bits, el ancho de `i64`; lo que
+    // no es entero se sigue viendo en decimal.
+    fn format_in_base(&self) -> String {
+        let value = self.shown_value();
+        match (self.base, to_integer(value)) {
+            (NumberBase::Hexadecimal, Some(integer)) => format!("0x{:x}", integer),
+            (NumberBase::Binary, Some(integer)) => format!("0b{:b}", integer),
+            _ => self.format_number(&value.to_string()),
+        }
+    }
+
+    // Solo cambia la presentación: el valor completo se conserva en `current_number`
+    fn format_number(&self, raw: &str) -> String {
+        let text = match raw.parse::<f64>() {
+            Ok(value) if value != 0.0 && value.is_finite() => {
+                let exponent = value.abs().log10().floor() as i32;
+                if exponent >= self.max_exponent || exponent < self.min_exponent {
+                    format!("{:e}", value)
+                } else {
+                    format_display(raw)
+                }
+            }
+            _ => format_display(raw),
+        };
+        self.localize(&text)
+    }
+
+    // Un valor con el mismo formato que tendría en pantalla; las sumas como
+    // el gran total se redondean aquí, cada sumando ya venía redondeado
+    pub fn format_value(&self, value: f64) -> String {
+        let value = round_to(value, self.precision, self.rounding_mode);
+        if self.currency_mode {
+            return self.format_currency(value);
+        }
+        self.format_number(&value.to_string())
+    }
+
+    // `1234.5` se ve `$1,234.50`; el signo va delante del símbolo
+    fn format_currency(&self, value: f64) -> String {
+        let fixed = format!("{:.2}", value.abs());
+        let sign = if value < 0.0 && fixed != "0.00" { "-" } else { "" };
+        format!("{}{}{}", sign, self.currency_symbol, self.localize(&format_display(&fixed)))
+    }
+
+    // Con coma decimal los miles se separan con punto: `1,234.5` pasa a `1.234,5`
+    fn localize(&self, text: &str) -> String {
+        if self.decimal_separator != ',' {
+            return text.to_string();
+        }
+        text.chars()
+            .map(|c| match c {
+                '.' => ',',
+                ',' => '.',
+                c => c,
+            })
+            .collect()
+    }
+
+    pub fn set_angle_mode(&mut self, angle_mode: AngleMode) {
+        self.angle_mode = angle_mode;
+    }
+
+    pub fn set_integer_mode(&mut self, integer_mode: bool) {
+        self.integer_mode = integer_mode;
+        if !integer_mode {
+            self.base = NumberBase::Decimal;
+        }
+        self.update_display();
+    }
+
+    pub fn base(&self) -> NumberBase {
+        self.base
+    }
+
+    pub fn set_auto_clear_on_error(&mut self, enabled: bool) {
+        self.auto_clear_on_error = enabled;
+    }
+
+    pub fn set_decimal_separator(&mut self, separator: char) {
+        self.decimal_separator = separator;
+        self.update_display();
+    }
+
+    pub fn set_currency_mode(&mut self, enabled: bool) {
+        self.currency_mode = enabled;
+        self.update_display();
+    }
+
+    pub fn set_currency_symbol(&mut self, symbol: &str) {
+        self.currency_symbol = symbol.to_string();
+        self.update_display();
+    }
+
+    pub fn set_precision(&mut self, precision: usize) {
+        self.precision = precision;
+    }
+
+    pub fn set_rounding_mode(&mut self, mode: RoundingMode) {
+        self.rounding_mode = mode;
+    }
+
+    pub fn set_max_magnitude(&mut self, max_magnitude: Option<f64>) {
+        self.max_magnitude = max_magnitude;
+    }
+
+    pub fn set_exponent_range(&mut self, min: i32, max: i32) {
+        (self.min_exponent, self.max_exponent) = if min <= max { (min, max) } else { (max, min) };
+        self.update_display();
+    }
+
+    pub fn set_random_range(&mut self, min: f64, max: f64) {
+        self.random_range = if min <= max { (min, max) } else { (max, min) };
+    }
+
+    pub fn set_tax_rate(&mut self, rate: f64) {
+        self.tax_rate = rate;
+    }
+
+    pub fn set_discount_rate(&mut self, rate: f64) {
+        self.discount_rate = rate;
+    }
+
+    // `=` fallaría seguro: se divide (o se hace el módulo) entre un 0 ya escrito
+    pub fn divides_by_zero(&self) -> bool {
+        matches!(self.tokens.last(), Some(Token::Op('/' | '%')))
+            && self.current_number.parse::<f64>() == Ok(0.0)
+    }
+
+    // La última pulsación se ignoró por superar `max_input_len`
+    pub fn hit_input_limit(&self) -> bool {
+        self.input_limited
+    }
+
+    pub fn get_display(&self) -> String {
+        self.display.clone()
+    }
+
+    // El último resultado con todos sus decimales, solo si la pantalla lo muestra redondeado
+    pub fn full_precision(&self) -> Option<String> {
+        self.unrounded.map(|value| self.localize(&value.to_string()))
+    }
+
+    pub fn get_expression(&self) -> String {
+        self.expression.clone()
+    }
+
+    // Primer fallo de la expresión a medias, con su posición (en caracteres)
+    // dentro de `get_expression`; un operador final no cuenta, aún se escribe
+    pub fn validate_expression(&self) -> Result<(), (usize, String)> {
+        let mut text = String::new();
+        let mut previous = None;
+        for token in &self.tokens {
+            let start = text.chars().count() + usize::from(matches!(token, Token::Op(_)));
+            text.push_str(&format_tokens_with(std::slice::from_ref(token), |number| {
+                self.format_number(&number.to_string())
+            }));
+            let error = match (previous, token) {
+                (Some(Token::Op(_)), Token::Op(_)) => Some("Dos operadores seguidos"),
+                (Some(Token::LParen), Token::Op(_)) => Some("Falta un número"),
+                (Some(Token::Op(_) | Token::LParen), Token::RParen) => Some("Falta un número"),
+                _ => None,
+            };
+            if let Some(message) = error {
+                let leading = text.chars().count() - text.trim_start().chars().count();
+                return Err((start - leading, message.to_string()));
+            }
+            previous = Some(*token);
+        }
+        Ok(())
+    }
+
+    // Sustituye el número que se está escribiendo, p. ej. con un resultado del historial
+    pub fn load_value(&mut self, value: f64) {
+        self.replace_with(value);
+        self.update_display();
+    }
+
+    // Texto escrito con el separador configurado, tal como lo lee `tokenize`:
+    // sin separador de miles y con punto decimal
+    fn delocalize(&self, text: &str) -> String {
+        let grouping = if self.decimal_separator == ',' { '.' } else { ',' };
+        text.chars()
+            .filter(|&c| c != grouping)
+            .map(|c| if c == self.decimal_separator { '.' } else { c })
+            .collect()
+    }
+
+    // Número pegado desde el portapapeles: admite espacios, el separador de
+    // miles y el decimal configurado, pero nada que no sea un número
+    pub fn paste_number(&mut self, text: &str) -> Result<(), String> {
+        let normalized: String = self.delocalize(text).chars().filter(|c| !c.is_whitespace()).collect();
+        let digits = normalized.strip_prefix('-').unwrap_or(&normalized);
+        let well_formed = digits.starts_with(|c: char| c.is_ascii_digit() || c == '.')
+            && digits.chars().all(|c| c.is_ascii_digit() || matches!(c, '.' | 'e' | 'E' | '+' | '-'));
+        match normalized.parse::<f64>() {
+            Ok(value) if well_formed && value.is_finite() => {
+                self.load_value(value);
+                Ok(())
+            }
+            _ => Err("El portapapeles no contiene un número".to_string()),
+        }
+    }
+
+    // Añade el valor como siguiente operando. Si no hay un operador pendiente
+    // (se está escribiendo un número o se acaba de cerrar un paréntesis) se
+    // multiplica, como `2Ans` en las calculadoras científicas.
+    pub fn insert_operand(&mut self, value: f64) -> Result<(), String> {
+        if std::mem::take(&mut self.just_evaluated) {
+            self.current_number.clear();
+        }
+        let operator_pending = self.current_number.is_empty()
+            && matches!(self.tokens.last(), None | Some(Token::Op(_) | Token::LParen));
+        if !operator_pending {
+            self.flush_number()?;
+            self.tokens.push(Token::Op('*'));
+        }
+        self.current_number = value.to_string();
+        self.update_display();
+        Ok(())
+    }
+
+    pub fn get_history(&self) -> Vec<HistoryEntry> {
+        self.history.clone()
+    }
+
+    // Anota en el rollo el número tecleado y la tecla que lo cierra, por
+    // delante de los resultados que haya añadido la propia tecla
+    fn print_key(&mut self, value: &str, typed: &str, tape_len: usize) {
+        let closes_number = matches!(
+            value,
+            "+" | "-" | "*" | "/" | "^" | "mod" | "and" | "or" | "xor" | "(" | ")" | "="
+        );
+        // Un `-` que sigue en `current_number` era el signo de un exponente
+        if !closes_number || (value == "-" && !self.current_number.is_empty()) {
+            return;
+        }
+        let line = if typed.is_empty() {
+            value.to_string()
+        } else {
+            format!("{} {}", self.format_number(typed), value)
+        };
+        self.tape.insert(tape_len, line);
+        self.trim_tape();
+    }
+
+    fn trim_tape(&mut self) {
+        if self.tape.len() > MAX_TAPE {
+            let excess = self.tape.len() - MAX_TAPE;
+            self.tape.drain(..excess);
+        }
+    }
+
+    pub fn get_tape(&self) -> Vec<String> {
+        self.tape.clone()
+    }
+
+    fn add_history(&mut self, expression: String, tokens: Vec<Token>, result: f64) {
+        self.tape.push(format!("= {}", self.format_number(&result.to_string())));
+        self.trim_tape();
+        self.history.push(HistoryEntry {
+            expression,
+            result,
+            timestamp: Some((self.clock)()),
+            tokens,
+        });
+        self.trim_history();
+    }
+
+    // Se descartan las entradas más antiguas al superar el límite
+    fn trim_history(&mut self) {
+        if self.history.len() > self.max_history {
+            let excess = self.history.len() - self.max_history;
+            self.history.drain(..excess);
+        }
+    }
+
+    pub fn set_max_history(&mut self, max_history: usize) {
+        self.max_history = max_history;
+        self.trim_history();
+    }
+
+    // Vuelve a cargar la expresión de una entrada para poder retocarla antes
+    // de pulsar `=`; el último operando queda en edición
+    pub fn rerun(&mut self, index: usize) -> Result<(), String> {
+        let entry = self.history.get(index).ok_or("Entrada inexistente")?;
+        let tokens = if entry.tokens.is_empty() {
+            // Entradas importadas de versiones anteriores
+            tokenize(&entry.expression).map_err(|_| "Esta entrada no se puede re-ejecutar")?
+        } else {
+            entry.tokens.clone()
+        };
+        self.reset();
+        self.tokens = tokens;
+        if let Some(&Token::Number(number)) = self.tokens.last() {
+            self.tokens.pop();
+            self.current_number = number.to_string();
+        }
+        self.just_evaluated = false;
+        self.update_display();
+        Ok(())
+    }
+
+    pub fn remove_history(&mut self, index: usize) {
+        if index < self.history.len() {
+            self.history.remove(index);
+        }
+    }
+
+    pub fn angle_mode(&self) -> AngleMode {
+        self.angle_mode
+    }
+
+    pub fn grand_total(&self) -> f64 {
+        self.grand_total
+    }
+
+    pub fn running_total(&self) -> f64 {
+        self.running_total
+    }
+
+    pub fn data_count(&self) -> usize {
+        self.data_points.len()
+    }
+
+    pub fn has_constant(&self) -> bool {
+        self.constant.is_some()
+    }
+
+    pub fn has_memory(&self) -> bool {
+        self.memory != 0.0
+    }
+
+    // La operación pendiente y su operando izquierdo salen del final de la expresión
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub fn snapshot(&self) -> CalculatorSnapshot {
+        let (previous_number, operation) = match self.tokens[..] {
+            [.., Token::Number(previous), Token::Op(op)] => (Some(previous), Some(op)),
+            [.., Token::Op(op)] => (None, Some(op)),
+            _ => (None, None),
+        };
+        CalculatorSnapshot {
+            display: self.display.clone(),
+            current_number: self.current_number.clone(),
+            operation,
+            previous_number,
+            history_len: self.history.len(),
+        }
+    }
+
+    pub fn state(&self) -> CalculatorState {
+        CalculatorState {
+            history: self.history.clone(),
+            memory: self.memory,
+            // Se guarda con punto decimal para poder leerlo con cualquier separador
+            display: if self.decimal_separator == ',' {
+                self.localize(&self.display)
+            } else {
+                self.display.clone()
+            },
+            operations_performed: self.operations_performed,
+        }
+    }
+
+    pub fn from_state(state: CalculatorState) -> Self {
+        let mut calc = Calculator {
+            history: state.history,
+            memory: state.memory,
+            operations_performed: state.operations_performed,
+            ..Calculator::new()
+        };
+        // Solo se recupera la pantalla si contiene un número válido
+        if let Ok(value) = state.display.replace(',', "").parse::<f64>() {
+            if value.is_finite() {
+                calc.load_value(value);
+            }
+        }
+        calc
+    }
+
+    // Operaciones hechas y entradas del historial
+    pub fn stats(&self) -> (usize, usize) {
+        (self.operations_performed, self.history.len())
+    }
+
+    pub fn history_sum(&self) -> f64 {
+        self.history.iter().map(|entry| entry.result).sum()
+    }
+
+    pub fn history_to_csv(&self) -> String {
+        let mut csv = String::from("expression,result\n");
+        for entry in &self.history {
+            csv.push_str(&format!("{},{}\n", csv_field(&entry.expression), entry.result));
+        }
+        csv
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(&self.state()).unwrap_or_default()
+    }
+
+    pub fn from_json(s: &str) -> Result<Calculator, String> {
+        serde_json::from_str::<CalculatorState>(s)
+            .map(Calculator::from_state)
+            .map_err(|_| String::from("Sesión inválida"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn push_all_stops_at_the_first_error() {
+        let mut calc = Calculator::new();
+        assert_eq!(calc.push_all(&["1", "/", "0", "=", "5"]), Err("División por cero".to_string()));
+        assert_eq!(calc.current_number, "0");
+    }
+
+    #[test]
+    fn adds_two_numbers() {
+        let mut calc = Calculator::new();
+        calc.push_all(&["7", "+", "3", "="]).unwrap();
+        assert_eq!(calc.get_display(), "10");
+    }
+
+    #[test]
+    fn subtracts_two_numbers() {
+        let mut calc = Calculator::new();
+        calc.push_all(&["7", "-", "9", "="]).unwrap();
+        assert_eq!(calc.get_display(), "-2");
+    }
+
+    #[test]
+    fn division_by_zero_is_an_error() {
+        let mut calc = Calculator::new();
+        let result = calc.push_all(&["5", "/", "0", "="]);
+        assert_eq!(result, Err("División por cero".to_string()));
+    }
+
+    #[test]
+    fn ac_resets_everything_but_history() {
+        let mut calc = Calculator::new();
+        calc.push_all(&["2", "+", "2", "=", "4", "*", "ac"]).unwrap();
+        assert_eq!(calc.get_display(), "0");
+        assert!(calc.current_number.is_empty());
+        assert!(calc.tokens.is_empty());
+        assert_eq!(calc.get_history().len(), 1);
+    }
+
+    #[test]
+    fn double_ac_also_clears_memory_and_history() {
+        let mut calc = Calculator::new();
+        calc.push_all(&["2", "+", "2", "=", "m+"]).unwrap();
+        calc.clock = || 1000.0;
+        calc.push_all(&["ac"]).unwrap();
+        calc.clock = || 3000.0;
+        calc.push_all(&["ac"]).unwrap();
+        assert!(calc.has_memory());
+        assert_eq!(calc.get_history().len(), 1);
+
+        calc.clock = || 3200.0;
+        calc.push_all(&["ac"]).unwrap();
+        assert!(!calc.has_memory());
+        assert!(calc.get_history().is_empty());
+    }
+
+    #[test]
+    fn a_key_between_two_acs_keeps_memory() {
+        let mut calc = Calculator::new();
+        calc.clock = || 1000.0;
+        calc.push_all(&["5", "m+", "ac", "5", "ac"]).unwrap();
+        assert!(calc.has_memory());
+        calc.push_all(&["undo", "ac"]).unwrap();
+        assert!(calc.has_memory());
+    }
+
+    #[test]
+    fn clear_entry_keeps_the_pending_operation() {
+        let mut calc = Calculator::new();
+        calc.push_all(&["5", "+", "3", "ce"]).unwrap();
+        assert_eq!(calc.get_expression(), "5 +");
+        calc.push_all(&["4", "="]).unwrap();
+        assert_eq!(calc.get_display(), "9");
+    }
+
+    #[test]
+    fn swaps_the_operands() {
+        let mut calc = Calculator::new();
+        calc.push_all(&["1", "0", "-", "3", "swap"]).unwrap();
+        assert_eq!(calc.get_expression(), "3 -");
+        calc.push("=").unwrap();
+        assert_eq!(calc.get_display(), "-7");
+
+        let mut calc = Calculator::new();
+        calc.push_all(&["1", "0", "swap"]).unwrap();
+        assert_eq!(calc.get_display(), "10");
+    }
+
+    #[test]
+    fn caps_the_length_of_the_input() {
+        let mut calc = Calculator::new();
+        calc.push_all(&["0", "."]).unwrap();
+        for _ in 0..30 {
+            calc.push("3").unwrap();
+        }
+        assert_eq!(calc.current_number.len(), 20);
+        assert!(calc.hit_input_limit());
+        calc.push_all(&["+", "1", "="]).unwrap();
+        assert_eq!(calc.get_display(), "1.3333333333");
+        assert!(!calc.hit_input_limit());
+
+        let mut calc = Calculator::new();
+        calc.max_input_len = 3;
+        calc.push_all(&["1", "2", "3", "4"]).unwrap();
+        assert_eq!(calc.current_number, "123");
+    }
+
+    #[test]
+    fn backspace_removes_last_digit() {
+        let mut calc = Calculator::new();
+        calc.push_all(&["1", "2", "3", "<"]).unwrap();
+        assert_eq!(calc.get_display(), "12");
+        calc.push_all(&["<", "<", "<"]).unwrap();
+        assert_eq!(calc.get_display(), "0");
+    }
+
+    #[test]
+    fn respects_operator_precedence() {
+        let mut calc = Calculator::new();
+        calc.push_all(&["2", "+", "3", "*", "4", "="]).unwrap();
+        assert_eq!(calc.get_display(), "14");
+        assert_eq!(calc.get_history()[0].to_string(), "2 + 3 * 4 = 14");
+    }
+
+    #[test]
+    fn rejects_malformed_expressions() {
+        let mut calc = Calculator::new();
+        assert!(calc.push_all(&["="]).is_err());
+        assert!(calc.push_all(&["2", "+", "="]).is_err());
+
+        let mut calc = Calculator::new();
+        assert!(calc.push_all(&["2", "+", "*", "3", "="]).is_err());
+    }
+
+    #[test]
+    fn operator_first_starts_from_zero() {
+        let mut calc = Calculator::new();
+        calc.push_all(&["+", "5", "="]).unwrap();
+        assert_eq!(calc.get_display(), "5");
+        assert_eq!(calc.get_history()[0].to_string(), "0 + 5 = 5");
+
+        let mut calc = Calculator::new();
+        calc.push_all(&["-", "3", "="]).unwrap();
+        assert_eq!(calc.get_display(), "-3");
+
+        let mut calc = Calculator::new();
+        calc.push_all(&["/"]).unwrap();
+        assert_eq!(calc.get_expression(), "0 /");
+        assert_eq!(calc.push_all(&["0", "="]), Err("División por cero".to_string()));
+    }
+
+    #[test]
+    fn a_digit_after_equals_starts_a_new_number() {
+        let mut calc = Calculator::new();
+        calc.push_all(&["1", "0", "+", "2", "=", "3"]).unwrap();
+        assert_eq!(calc.get_display(), "3");
+        calc.push_all(&["+", "1", "="]).unwrap();
+        assert_eq!(calc.get_display(), "4");
+
+        let mut calc = Calculator::new();
+        calc.push_all(&["1", "0", "+", "2", "=", "*", "2", "="]).unwrap();
+        assert_eq!(calc.get_display(), "24");
+        assert_eq!(calc.get_history()[1].to_string(), "12 * 2 = 24");
+    }
+
+    #[test]
+    fn a_digit_after_a_function_or_constant_starts_a_new_number() {
+        let mut calc = Calculator::new();
+        calc.push_all(&["1", "6", "sqrt", "2"]).unwrap();
+        assert_eq!(calc.get_display(), "2");
+        calc.push_all(&["ac", "1", "6", "sqrt", "+", "1", "="]).unwrap();
+        assert_eq!(calc.get_display(), "5");
+
+        let mut calc = Calculator::new();
+        calc.push_all(&["pi", "2"]).unwrap();
+        assert_eq!(calc.get_display(), "2");
+
+        // `=` tras una función no repite la última operación
+        let mut calc = Calculator::new();
+        calc.push_all(&["2", "+", "3", "=", "1", "6", "sqrt", "="]).unwrap();
+        assert_eq!(calc.get_display(), "4");
+
+        let mut calc = Calculator::new();
+        calc.push_all(&["2", "+", "3", "=", "undo", "redo", "4"]).unwrap();
+        assert_eq!(calc.get_display(), "4");
+    }
+
+    #[test]
+    fn keys_that_leave_the_entry_alone_keep_a_result_replaceable() {
+        for key in ["m+", "frac", "angle", "exp_input"] {
+            let mut calc = Calculator::new();
+            calc.push_all(&["2", "+", "3", "=", key, "7"]).unwrap();
+            assert_eq!(calc.get_display(), "7", "tecla {}", key);
+        }
+
+        let mut calc = Calculator::new();
+        calc.push_all(&["1", "6", "sqrt", "m+", "7"]).unwrap();
+        assert_eq!(calc.get_display(), "7");
+
+        let mut calc = Calculator::new();
+        calc.set_integer_mode(true);
+        calc.push_all(&["2", "+", "3", "=", "hex", "7"]).unwrap();
+        assert_eq!(calc.get_display(), "0x7");
+    }
+
+    #[test]
+    fn repeated_equals_repeats_the_last_operation() {
+        let mut calc = Calculator::new();
+        calc.push_all(&["2", "+", "3", "=", "="]).unwrap();
+        assert_eq!(calc.get_display(), "8");
+        calc.push_all(&["="]).unwrap();
+        assert_eq!(calc.get_display(), "11");
+        assert_eq!(calc.get_history()[2].to_string(), "8 + 3 = 11");
+
+        calc.push_all(&["5", "="]).unwrap();
+        assert_eq!(calc.get_display(), "5");
+    }
+
+    #[test]
+    fn ans_inserts_the_previous_result() {
+        let mut calc = Calculator::new();
+        calc.push_all(&["ans"]).unwrap();
+        assert_eq!(calc.get_display(), "0");
+
+        calc.push_all(&["6", "*", "7", "=", "ac", "1", "+", "ans"]).unwrap();
+        assert_eq!(calc.get_display(), "42");
+        calc.push_all(&["=", "ans", "*", "2", "="]).unwrap();
+        assert_eq!(calc.get_display(), "86");
+    }
+
+    #[test]
+    fn chained_operators_show_the_pending_result() {
+        let mut calc = Calculator::new();
+        calc.push_all(&["3", "+", "4", "+"]).unwrap();
+        assert_eq!(calc.get_display(), "7");
+        calc.push_all(&["5", "="]).unwrap();
+        assert_eq!(calc.get_display(), "12");
+        assert_eq!(calc.get_history()[0].to_string(), "3 + 4 + 5 = 12");
+
+        let mut calc = Calculator::new();
+        calc.push_all(&["2", "+", "3", "*"]).unwrap();
+        assert_eq!(calc.get_display(), "3");
+        calc.push_all(&["4", "-"]).unwrap();
+        assert_eq!(calc.get_display(), "14");
+    }
+
+    #[test]
+    fn accumulates_the_expression_as_it_is_entered() {
+        let mut calc = Calculator::new();
+        calc.push_all(&["7", "+", "3", "*"]).unwrap();
+        assert_eq!(calc.get_expression(), "7 + 3 *");
+        calc.push_all(&["(", "1", "2", "0", "0"]).unwrap();
+        assert_eq!(calc.get_expression(), "7 + 3 * (");
+        assert_eq!(calc.get_display(), "1,200");
+        calc.push_all(&[")", "="]).unwrap();
+        assert_eq!(calc.get_expression(), "");
+        assert_eq!(calc.get_history()[0].expression, "7 + 3 * (1200)");
+
+        calc.push_all(&["+", "1", "ac"]).unwrap();
+        assert_eq!(calc.get_expression(), "");
+    }
+
+    #[test]
+    fn backspace_steps_back_over_an_operator() {
+        let mut calc = Calculator::new();
+        calc.push_all(&["5", "+", "<"]).unwrap();
+        assert_eq!(calc.get_expression(), "");
+        assert_eq!(calc.current_number, "5");
+        calc.push_all(&["*", "2", "="]).unwrap();
+        assert_eq!(calc.get_display(), "10");
+
+        let mut calc = Calculator::new();
+        calc.push_all(&["(", "<", "<"]).unwrap();
+        assert_eq!(calc.paren_depth, 0);
+        assert_eq!(calc.get_display(), "0");
+    }
+
+    #[test]
+    fn evaluates_parentheses() {
+        let mut calc = Calculator::new();
+        calc.push_all(&["(", "2", "+", "3", ")"]).unwrap();
+        assert_eq!(calc.get_display(), "5");
+        calc.push_all(&["*", "4"]).unwrap();
+        assert_eq!(calc.get_display(), "4");
+        calc.push_all(&["="]).unwrap();
+        assert_eq!(calc.get_display(), "20");
+    }
+
+    #[test]
+    fn unbalanced_parentheses_are_an_error() {
+        let mut calc = Calculator::new();
+        let result = calc.push_all(&["(", "2", "+", "3", "="]);
+        assert_eq!(result, Err("Paréntesis sin cerrar".to_string()));
+    }
+
+    #[test]
+    fn detects_a_pending_division_by_zero() {
+        let mut calc = Calculator::new();
+        calc.push_all(&["8", "/"]).unwrap();
+        assert!(!calc.divides_by_zero());
+        calc.push_all(&["0", "."]).unwrap();
+        assert!(calc.divides_by_zero());
+        calc.push("5").unwrap();
+        assert!(!calc.divides_by_zero());
+    }
+
+    #[test]
+    fn allows_a_single_decimal_point() {
+        let mut calc = Calculator::new();
+        calc.push_all(&["."]).unwrap();
+        assert_eq!(calc.get_display(), "0.");
+
+        let mut calc = Calculator::new();
+        calc.push_all(&["1", ".", "."]).unwrap();
+        assert_eq!(calc.get_display(), "1.");
+
+        let mut calc = Calculator::new();
+        calc.push_all(&["1", ".", "2", ".", "3"]).unwrap();
+        assert_eq!(calc.get_display(), "1.23");
+    }
+
+    #[test]
+    fn drops_leading_zeros() {
+        let mut calc = Calculator::new();
+        calc.push_all(&["0", "7"]).unwrap();
+        assert_eq!(calc.current_number, "7");
+
+        let mut calc = Calculator::new();
+        calc.push_all(&["0", "0"]).unwrap();
+        assert_eq!(calc.current_number, "0");
+
+        let mut calc = Calculator::new();
+        calc.push_all(&["0", ".", "5"]).unwrap();
+        assert_eq!(calc.current_number, "0.5");
+    }
+
+    #[test]
+    fn reports_where_the_expression_is_invalid() {
+        let mut calc = Calculator::new();
+        calc.push_all(&["2", "+"]).unwrap();
+        assert_eq!(calc.validate_expression(), Ok(()));
+        calc.push_all(&["*"]).unwrap();
+        assert_eq!(calc.get_expression(), "2 +  *");
+        assert_eq!(calc.validate_expression(), Err((5, String::from("Dos operadores seguidos"))));
+
+        let mut calc = Calculator::new();
+        calc.push_all(&["1", "2", "3", "4", "-", "5", "mod", "/"]).unwrap();
+        assert_eq!(calc.get_expression(), "1,234 - 5 mod  /");
+        assert_eq!(calc.validate_expression(), Err((15, String::from("Dos operadores seguidos"))));
+
+        let mut calc = Calculator::new();
+        calc.push_all(&["(", "*"]).unwrap();
+        assert_eq!(calc.validate_expression(), Err((2, String::from("Falta un número"))));
+    }
+
+    #[test]
+    fn enters_numbers_in_scientific_notation() {
+        let mut calc = Calculator::new();
+        calc.push_all(&["1", ".", "5", "exp_input", "3"]).unwrap();
+        assert_eq!(calc.current_number, "1.5e3");
+        calc.push_all(&["exp_input", ".", "+", "1", "="]).unwrap();
+        assert_eq!(calc.get_display(), "1,501");
+
+        let mut calc = Calculator::new();
+        calc.push_all(&["exp_input"]).unwrap();
+        assert!(calc.current_number.is_empty());
+    }
+
+    #[test]
+    fn enters_negative_exponents() {
+        let mut calc = Calculator::new();
+        calc.push_all(&["1", ".", "5", "exp_input", "-", "3"]).unwrap();
+        assert_eq!(calc.current_number, "1.5e-3");
+        calc.push_all(&["+", "1", "="]).unwrap();
+        assert_eq!(calc.get_display(), "1.0015");
+
+        // Fuera del exponente el menos sigue siendo la resta
+        let mut calc = Calculator::new();
+        calc.push_all(&["2", "exp_input", "2", "-", "5", "="]).unwrap();
+        assert_eq!(calc.get_display(), "195");
+    }
+
+    #[test]
+    fn auto_clear_on_error_starts_the_next_input_clean() {
+        let mut calc = Calculator::new();
+        assert!(calc.push_all(&["5", "+", "3", "/", "0", "="]).is_err());
+        // Sin la opción el 2 continúa la entrada a medias: 5 + 3 / 2
+        calc.push_all(&["2", "="]).unwrap();
+        assert_eq!(calc.get_display(), "6.5");
+
+        let mut calc = Calculator::new();
+        calc.set_auto_clear_on_error(true);
+        assert!(calc.push_all(&["5", "+", "3", "/", "0", "="]).is_err());
+        assert_eq!(calc.get_display(), "0");
+        calc.push_all(&["2", "="]).unwrap();
+        assert_eq!(calc.get_display(), "2");
+    }
+
+    #[test]
+    fn pastes_only_well_formed_numbers() {
+        let mut calc = Calculator::new();
+        calc.paste_number(" 1,234.5 ").unwrap();
+        assert_eq!(calc.current_number, "1234.5");
+        calc.paste_number("-2.5e3").unwrap();
+        assert_eq!(calc.current_number, "-2500");
+        for text in ["12abc", "inf", "NaN", "", "--1", "1.2.3"] {
+            assert!(calc.paste_number(text).is_err(), "{:?}", text);
+        }
+        assert_eq!(calc.current_number, "-2500");
+
+        calc.set_decimal_separator(',');
+        calc.paste_number("1.234,5").unwrap();
+        assert_eq!(calc.current_number, "1234.5");
+    }
+
+    #[test]
+    fn a_digit_after_a_loaded_value_starts_a_new_number() {
+        let mut calc = Calculator::new();
+        calc.load_value(12.0);
+        calc.push("3").unwrap();
+        assert_eq!(calc.get_display(), "3");
+
+        let mut calc = Calculator::new();
+        calc.paste_number("12").unwrap();
+        calc.push_all(&["3"]).unwrap();
+        assert_eq!(calc.get_display(), "3");
+        // Un operador sí sigue con el valor cargado
+        calc.paste_number("12").unwrap();
+        calc.push_all(&["+", "1", "="]).unwrap();
+        assert_eq!(calc.get_display(), "13");
+    }
+
+    #[test]
+    fn rejects_unknown_commands() {
+        let mut calc = Calculator::new();
+        calc.push("1").unwrap();
+        assert_eq!(calc.push("foo"), Err("Tecla desconocida: 'foo'".to_string()));
+        assert!(calc.push("23").is_err());
+        assert_eq!(calc.get_display(), "1");
+    }
+
+    #[test]
+    fn corrupt_number_is_an_error_not_a_panic() {
+        let mut calc = Calculator::new();
+        calc.current_number = String::from("1..2");
+        assert_eq!(calc.push("+"), Err("Número inválido".to_string()));
+
+        let mut calc = Calculator::new();
+        calc.push_all(&["2", "+"]).unwrap();
+        calc.current_number = String::from("x");
+        assert_eq!(calc.push("="), Err("Número inválido".to_string()));
+    }
+
+    #[test]
+    fn memory_register() {
+        let mut calc = Calculator::new();
+        calc.push_all(&["5", "m+", "ac", "3", "m-", "mr"]).unwrap();
+        assert_eq!(calc.get_display(), "2");
+        assert!(calc.has_memory());
+        calc.push_all(&["mc"]).unwrap();
+        assert!(!calc.has_memory());
+    }
+
+    #[test]
+    fn memory_adds_the_running_value() {
+        let mut calc = Calculator::new();
+        calc.push_all(&["3", "+", "4", "+"]).unwrap();
+        assert_eq!(calc.get_display(), "7");
+        calc.push_all(&["m+", "ac", "mr"]).unwrap();
+        assert_eq!(calc.get_display(), "7");
+    }
+
+    #[test]
+    fn grand_total_accumulates_results() {
+        let mut calc = Calculator::new();
+        calc.push_all(&["2", "+", "3", "=", "ac", "4", "*", "5", "="]).unwrap();
+        assert_eq!(calc.grand_total(), 25.0);
+        calc.push_all(&["ac", "gt"]).unwrap();
+        assert_eq!(calc.get_display(), "25");
+        calc.push_all(&["gt_clear"]).unwrap();
+        assert_eq!(calc.grand_total(), 0.0);
+
+        calc.push_all(&["ac", "0", ".", "1", "+", "0", "=", "0", ".", "2", "+", "0", "="]).unwrap();
+        assert_eq!(calc.format_value(calc.grand_total()), "0.3");
+        calc.push_all(&["gt"]).unwrap();
+        assert_eq!(calc.get_display(), "0.3");
+    }
+
+    #[test]
+    fn quick_mode_adds_each_entry_to_the_running_total() {
+        let mut calc = Calculator::new();
+        calc.push_all(&["1", "2", "total_add", "3", ".", "5", "total_add"]).unwrap();
+        assert_eq!(calc.running_total(), 15.5);
+        assert_eq!(calc.get_display(), "0");
+        calc.push_all(&["total_reset"]).unwrap();
+        assert_eq!(calc.running_total(), 0.0);
+
+        // Deshacer devuelve el número a la entrada y lo quita del total
+        calc.push_all(&["1", "2", "total_add", "undo"]).unwrap();
+        assert_eq!(calc.get_display(), "12");
+        assert_eq!(calc.running_total(), 0.0);
+        calc.push_all(&["total_add"]).unwrap();
+        assert_eq!(calc.running_total(), 12.0);
+    }
+
+    #[test]
+    fn prints_every_entry_on_the_tape() {
+        let mut calc = Calculator::new();
+        calc.push_all(&["1", "2", "+", "3", "*", "2", "=", "sqrt"]).unwrap();
+        assert_eq!(calc.get_tape(), ["12 +", "3 *", "2 =", "= 18", "= 4.2426406871"]);
+
+        // AC no toca el rollo; solo su propio borrado
+        calc.push_all(&["ac", "(", "1", ")"]).unwrap();
+        assert_eq!(calc.get_tape()[5..], ["(", "1 )"]);
+        calc.push_all(&["tape_clear"]).unwrap();
+        assert!(calc.get_tape().is_empty());
+    }
+
+    #[test]
+    fn converts_between_decimal_degrees_and_dms() {
+        assert_eq!(to_dms(30.5), (30, 30, 0.0));
+        assert_eq!(to_dms(-12.755), (-12, 45, 18.0));
+        assert_eq!(from_dms(30.0, 30.0, 0.0), 30.5);
+        assert_eq!(format_dms(from_dms(30.0, 0.0, 5.5)), "30°00'05.5\"");
+        assert_eq!(format_dms(from_dms(30.0, 0.0, 12.25)), "30°00'12.25\"");
+
+        let mut calc = Calculator::new();
+        calc.push_all(&["3", "0", ".", "5", "dms"]).unwrap();
+        assert_eq!(calc.get_display(), "30°30'00\"");
+        calc.push_all(&["dms"]).unwrap();
+        assert_eq!(calc.get_display(), "30.5");
+
+        let mut calc = Calculator::new();
+        calc.push_all(&["3", "0", "dms_deg", "3", "0", "dms_min"]).unwrap();
+        assert_eq!(calc.get_display(), "30°30'00\"");
+        calc.push_all(&["0", "dms_sec", "+", "1", "="]).unwrap();
+        assert_eq!(calc.get_display(), "31.5");
+
+        // Sin ″ lo último tecleado son los minutos
+        calc.push_all(&["ac", "3", "0", "dms_deg", "1", "5", "="]).unwrap();
+        assert_eq!(calc.current_number, "30.25");
+    }
+
+    #[test]
+    fn dms_follows_the_angle_mode() {
+        let mut calc = Calculator::new();
+        calc.set_angle_mode(AngleMode::Radians);
+        calc.push_all(&["pi", "/", "2", "=", "dms"]).unwrap();
+        assert_eq!(calc.get_display(), "90°00'00\"");
+        calc.push_all(&["ac", "1", "8", "0", "dms_deg", "="]).unwrap();
+        assert_eq!(calc.current_number, std::f64::consts::PI.to_string());
+    }
+
+    #[test]
+    fn clear_history_keeps_the_current_entry() {
+        let mut calc = Calculator::new();
+        calc.push_all(&["1", "+", "1", "=", "5", "clear_history"]).unwrap();
+        assert!(calc.get_history().is_empty());
+        assert_eq!(calc.current_number, "5");
+        assert_eq!(calc.get_display(), "5");
+    }
+
+    #[test]
+    fn keeps_only_the_most_recent_history() {
+        let mut calc = Calculator::new();
+        for i in 0..105 {
+            calc.evaluate_expression(&format!("{}+0", i)).unwrap();
+        }
+        let history = calc.get_history();
+        assert_eq!(history.len(), 100);
+        assert_eq!(history[0].result, 5.0);
+        assert_eq!(history[99].result, 104.0);
+
+        calc.set_max_history(10);
+        assert_eq!(calc.get_history()[0].result, 95.0);
+    }
+
+    #[test]
+    fn reruns_a_stored_expression() {
+        let mut calc = Calculator::new();
+        calc.push_all(&["2", "+", "3", "=", "ac"]).unwrap();
+        calc.rerun(0).unwrap();
+        assert_eq!(calc.get_expression(), "2 +");
+        assert_eq!(calc.get_display(), "3");
+        calc.push_all(&["<", "4", "="]).unwrap();
+        assert_eq!(calc.get_display(), "6");
+
+        calc.push("sqr").unwrap();
+        assert!(calc.rerun(2).is_err());
+        assert!(calc.rerun(9).is_err());
+    }
+
+    #[test]
+    fn removes_a_single_history_entry() {
+        let mut calc = Calculator::new();
+        calc.push_all(&["1", "+", "1", "=", "ac", "2", "+", "2", "="]).unwrap();
+        calc.remove_history(0);
+        calc.remove_history(5);
+        assert_eq!(calc.get_history()[0].result, 4.0);
+    }
+
+    #[test]
+    fn takes_a_snapshot_of_the_state() {
+        let mut calc = Calculator::new();
+        calc.push_all(&["2", "+", "3", "=", "*", "4"]).unwrap();
+        assert_eq!(
+            calc.snapshot(),
+            CalculatorSnapshot {
+                display: String::from("4"),
+                current_number: String::from("4"),
+                operation: Some('*'),
+                previous_number: Some(5.0),
+                history_len: 1,
+            }
+        );
+    }
+
+    #[test]
+    fn round_trips_a_session_through_json() {
+        let mut calc = Calculator::new();
+        calc.push_all(&["1", "2", "0", "0", "+", "3", "=", "m+"]).unwrap();
+        let restored = Calculator::from_json(&calc.to_json()).unwrap();
+        assert_eq!(restored.get_history(), calc.get_history());
+        assert_eq!(restored.get_display(), "1,203");
+        assert!(restored.has_memory());
+
+        assert!(Calculator::from_json("{\"history\": 3}").is_err());
+    }
+
+    #[test]
+    fn stamps_history_entries_with_the_clock() {
+        let mut calc = Calculator::new();
+        calc.clock = || 1000.0;
+        calc.push_all(&["1", "+", "1", "="]).unwrap();
+        assert_eq!(calc.get_history()[0].timestamp, Some(1000.0));
+
+        let json = r#"{"history": [{"expression": "1 + 1", "result": 2.0}], "memory": 0.0}"#;
+        let restored = Calculator::from_json(json).unwrap();
+        assert_eq!(restored.get_history()[0].timestamp, None);
+    }
+
+    #[test]
+    fn exports_history_entries_as_latex() {
+        let mut calc = Calculator::new();
+        calc.push_all(&["(", "1", "+", "2", ")", "/", "4", "="]).unwrap();
+        calc.push_all(&["9", "sqrt"]).unwrap();
+        calc.push_all(&["ac", "2", "*", "(", "5", "-", "3", ")", "="]).unwrap();
+        let history = calc.get_history();
+        assert_eq!(history[0].to_latex(), "\\frac{1 + 2}{4} = 0.75");
+        assert_eq!(history[1].to_latex(), "\\sqrt{9} = 3");
+        assert_eq!(history[2].to_latex(), "2 \\cdot \\left(5 - 3\\right) = 4");
+    }
+
+    #[test]
+    fn copies_the_result_or_the_whole_entry() {
+        let mut calc = Calculator::new();
+        calc.push_all(&["7", "-", "9", ".", "5", "="]).unwrap();
+        let entry = &calc.get_history()[0];
+        assert_eq!(entry.copy_text(CopyMode::ResultOnly), "-2.5");
+        assert_eq!(entry.copy_text(CopyMode::FullExpression), "7 - 9.5 = -2.5");
+    }
+
+    #[test]
+    fn sums_every_history_result() {
+        let mut calc = Calculator::new();
+        calc.push_all(&["2", "+", "3", "="]).unwrap();
+        calc.push_all(&["4", "-", "1", "0", "="]).unwrap();
+        calc.push_all(&["1", "6", "sqrt"]).unwrap();
+        assert_eq!(calc.history_sum(), 3.0);
+    }
+
+    #[test]
+    fn exports_history_as_csv() {
+        let mut calc = Calculator::new();
+        calc.push_all(&["1", "+", "1", "=", "ac", "2", "0", "0", "0", "*", "2", "="]).unwrap();
+        assert_eq!(
+            calc.history_to_csv(),
+            "expression,result\n1 + 1,2\n2000 * 2,4000\n"
+        );
+        assert_eq!(csv_field("1,5 + 2"), "\"1,5 + 2\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+
+    #[test]
+    fn inserts_a_history_result_as_the_next_operand() {
+        let mut calc = Calculator::new();
+        calc.push_all(&["2", "+"]).unwrap();
+        calc.insert_operand(40.0).unwrap();
+        calc.push_all(&["="]).unwrap();
+        assert_eq!(calc.get_display(), "42");
+
+        let mut calc = Calculator::new();
+        calc.push_all(&["3"]).unwrap();
+        calc.insert_operand(5.0).unwrap();
+        assert_eq!(calc.get_expression(), "3 *");
+        calc.push_all(&["="]).unwrap();
+        assert_eq!(calc.get_display(), "15");
+
+        calc.insert_operand(7.0).unwrap();
+        assert_eq!(calc.get_expression(), "");
+        assert_eq!(calc.get_display(), "7");
+    }
+
+    #[test]
+    fn adds_and_removes_tax() {
+        let mut calc = Calculator::new();
+        calc.set_tax_rate(16.0);
+        calc.push_all(&["1", "0", "0", "tax+"]).unwrap();
+        assert_eq!(calc.get_display(), "116");
+        assert_eq!(calc.get_history()[0].to_string(), "100 +16% = 116");
+
+        calc.push_all(&["tax-"]).unwrap();
+        assert_eq!(calc.get_display(), "100");
+        assert_eq!(calc.get_history()[1].to_string(), "116 -16% = 100");
+    }
+
+    #[test]
+    fn applies_discounts_and_recovers_the_original_price() {
+        let mut calc = Calculator::new();
+        calc.set_discount_rate(20.0);
+        calc.push_all(&["1", "0", "0", "discount"]).unwrap();
+        assert_eq!(calc.get_display(), "80");
+        assert_eq!(calc.get_history()[0].to_string(), "100 -20% dto = 80");
+
+        calc.push_all(&["original_price"]).unwrap();
+        assert_eq!(calc.get_display(), "100");
+
+        calc.push_all(&["markup"]).unwrap();
+        assert_eq!(calc.get_display(), "120");
+
+        calc.set_discount_rate(100.0);
+        assert!(calc.push_all(&["original_price"]).is_err());
+    }
+
+    #[test]
+    fn splits_the_bill_with_the_tip() {
+        assert_eq!(tip_split(100.0, 18.0, 4), Ok(29.5));
+        assert_eq!(tip_split(60.0, 0.0, 3), Ok(20.0));
+        assert!(tip_split(100.0, 18.0, 0).is_err());
+    }
+
+    #[test]
+    fn applies_absolute_value_and_standalone_percent() {
+        let mut calc = Calculator::new();
+        calc.push_all(&["5", "neg", "abs"]).unwrap();
+        assert_eq!(calc.get_display(), "5");
+        assert_eq!(calc.get_history()[0].to_string(), "|-5| = 5");
+
+        calc.push_all(&["ac", "5", "0", "pct"]).unwrap();
+        assert_eq!(calc.get_display(), "0.5");
+        assert_eq!(calc.get_history()[1].to_string(), "50% = 0.5");
+    }
+
+    #[test]
+    fn computes_percentage_change_and_ratio() {
+        let mut calc = Calculator::new();
+        calc.push_all(&["5", "0", "-", "7", "5", "pct_change"]).unwrap();
+        assert_eq!(calc.get_display(), "50");
+        assert_eq!(calc.get_history()[0].to_string(), "Δ% 50 → 75 = 50");
+
+        calc.push_all(&["ac", "4", "/", "2", "ratio"]).unwrap();
+        assert_eq!(calc.get_display(), "2:1");
+        assert_eq!(calc.get_history()[1].to_string(), "4:2 → 2:1 = 2");
+        calc.push_all(&["+", "1", "="]).unwrap();
+        assert_eq!(calc.get_display(), "3");
+
+        let mut calc = Calculator::new();
+        assert!(calc.push_all(&["0", "-", "5", "pct_change"]).is_err());
+        assert!(calc.push_all(&["ac", "5", "ratio"]).is_err());
+
+        let min = i64::MIN as f64;
+        assert!(ratio_label(min, min).ends_with("→ -1:-1"));
+        assert!(ratio_label(min, -1.0).ends_with("→ -9223372036854775808:-1"));
+    }
+
+    #[test]
+    fn finds_simple_fractions() {
+        assert_eq!(to_fraction(0.5), Some((1, 2)));
+        assert_eq!(to_fraction(1.0 / 3.0), Some((1, 3)));
+        assert_eq!(to_fraction(0.3333333333), Some((1, 3)));
+        assert_eq!(to_fraction(-0.75), Some((-3, 4)));
+        assert_eq!(to_fraction(2.0_f64.sqrt()), None);
+        assert_eq!(to_fraction(3.0), None);
+    }
+
+    #[test]
+    fn toggles_the_display_to_a_fraction() {
+        let mut calc = Calculator::new();
+        calc.push_all(&["1", "/", "4", "=", "frac"]).unwrap();
+        assert_eq!(calc.get_display(), "1/4");
+        calc.push("frac").unwrap();
+        assert_eq!(calc.get_display(), "0.25");
+
+        calc.push_all(&["frac", "+", "1", "="]).unwrap();
+        assert_eq!(calc.get_display(), "1.25");
+
+        calc.push_all(&["ac", "2", "frac"]).unwrap();
+        assert_eq!(calc.get_display(), "2");
+    }
+
+    #[test]
+    fn computes_statistics_over_data_points() {
+        let mut calc = Calculator::new();
+        assert_eq!(calc.push("mean"), Err("No hay datos".to_string()));
+
+        calc.push_all(&["2", "data", "4", "data", "9", "data"]).unwrap();
+        assert_eq!(calc.data_count(), 3);
+        calc.push("mean").unwrap();
+        assert_eq!(calc.get_display(), "5");
+        calc.push("sum").unwrap();
+        assert_eq!(calc.get_display(), "15");
+        calc.push("count").unwrap();
+        assert_eq!(calc.get_display(), "3");
+        assert_eq!(calc.get_history()[0].to_string(), "mean(n=3) = 5");
+
+        calc.push("data_clear").unwrap();
+        assert_eq!(calc.data_count(), 0);
+
+        // Deshacer `data` devuelve el número a la entrada sin dejar el dato
+        calc.push_all(&["ac", "4", "data", "undo"]).unwrap();
+        assert_eq!(calc.get_display(), "4");
+        assert_eq!(calc.data_count(), 0);
+        calc.push_all(&["data", "sum"]).unwrap();
+        assert_eq!(calc.get_display(), "4");
+    }
+
+    #[test]
+    fn percent_is_relative_to_the_running_value() {
+        let mut calc = Calculator::new();
+        calc.push_all(&["5", "0", "+", "1", "0", "%", "="]).unwrap();
+        assert_eq!(calc.get_display(), "55");
+
+        let mut calc = Calculator::new();
+        calc.push_all(&["5", "0", "%"]).unwrap();
+        assert_eq!(calc.get_display(), "0.5");
+    }
+
+    #[test]
+    fn backspace_removes_a_lone_sign() {
+        let mut calc = Calculator::new();
+        calc.push_all(&["5", "neg", "<"]).unwrap();
+        assert_eq!(calc.get_display(), "0");
+        calc.push_all(&["+", "2", "="]).unwrap();
+        assert_eq!(calc.get_display(), "2");
+    }
+
+    #[test]
+    fn toggles_the_sign() {
+        let mut calc = Calculator::new();
+        calc.push_all(&["0", "neg"]).unwrap();
+        assert_eq!(calc.get_display(), "0");
+
+        let mut calc = Calculator::new();
+        calc.push_all(&["5", "neg"]).unwrap();
+        assert_eq!(calc.get_display(), "-5");
+        calc.push_all(&["neg"]).unwrap();
+        assert_eq!(calc.get_display(), "5");
+
+        let mut calc = Calculator::new();
+        calc.push_all(&["5", "*", "3", "neg", "="]).unwrap();
+        assert_eq!(calc.get_display(), "-15");
+
+        let mut calc = Calculator::new();
+        calc.push_all(&["5", "+", "0", "=", "neg", "3"]).unwrap();
+        assert_eq!(calc.get_display(), "3");
+    }
+
+    #[test]
+    fn square_root_and_square() {
+        let mut calc = Calculator::new();
+        calc.push_all(&["1", "6", "sqrt"]).unwrap();
+        assert_eq!(calc.get_display(), "4");
+        assert_eq!(calc.get_history()[0].to_string(), "√16 = 4");
+        calc.push_all(&["sqr"]).unwrap();
+        assert_eq!(calc.get_display(), "16");
+
+        let mut calc = Calculator::new();
+        let result = calc.push_all(&["4", "neg", "sqrt"]);
+        assert_eq!(result, Err("Raíz de negativo".to_string()));
+    }
+
+    #[test]
+    fn reciprocal() {
+        let mut calc = Calculator::new();
+        calc.push_all(&["8", "recip"]).unwrap();
+        assert_eq!(calc.get_display(), "0.125");
+        assert_eq!(calc.get_history()[0].to_string(), "1/8 = 0.125");
+
+        let mut calc = Calculator::new();
+        let result = calc.push_all(&["0", "recip"]);
+        assert_eq!(result, Err("División por cero".to_string()));
+    }
+
+    #[test]
+    fn trigonometry_in_degrees_and_radians() {
+        let mut calc = Calculator::new();
+        calc.push_all(&["3", "0", "sin"]).unwrap();
+        assert_eq!(calc.get_display(), "0.5");
+        assert_eq!(calc.get_history()[0].to_string(), "sin(30) = 0.5");
+
+        let mut calc = Calculator::new();
+        calc.push_all(&["0", "cos"]).unwrap();
+        assert_eq!(calc.get_display(), "1");
+
+        let mut calc = Calculator::new();
+        calc.push_all(&["angle"]).unwrap();
+        assert_eq!(calc.angle_mode(), AngleMode::Radians);
+        calc.load_value(std::f64::consts::PI / 6.0);
+        calc.push_all(&["sin"]).unwrap();
+        assert_eq!(calc.get_display(), "0.5");
+
+        let mut calc = Calculator::new();
+        let result = calc.push_all(&["9", "0", "tan"]);
+        assert_eq!(result, Err("Desbordamiento".to_string()));
+    }
+
+    #[test]
+    fn logarithms_and_exponential() {
+        let mut calc = Calculator::new();
+        calc.push_all(&["1", "0", "0", "log"]).unwrap();
+        assert_eq!(calc.get_history()[0].to_string(), "log(100) = 2");
+        calc.push_all(&["ac", "1", "ln", "exp"]).unwrap();
+        assert_eq!(calc.get_display(), "1");
+
+        let mut calc = Calculator::new();
+        let result = calc.push_all(&["5", "neg", "log"]);
+        assert_eq!(result, Err("Logaritmo de no-positivo".to_string()));
+        let result = calc.push_all(&["ac", "0", "ln"]);
+        assert_eq!(result, Err("Logaritmo de no-positivo".to_string()));
+    }
+
+    #[test]
+    fn constants_keep_full_precision() {
+        let mut calc = Calculator::new();
+        calc.push_all(&["1", "pi", "*", "2", "="]).unwrap();
+        let result = calc.get_history()[0].result;
+        assert!((result - std::f64::consts::TAU).abs() < 1e-6);
+
+        let mut calc = Calculator::new();
+        calc.push_all(&["e", "ln"]).unwrap();
+        assert_eq!(calc.get_display(), "1");
+    }
+
+    #[test]
+    fn generates_random_numbers_in_range() {
+        let mut calc = Calculator::new();
+        calc.random = || 0.25;
+        calc.set_random_range(20.0, 10.0);
+        calc.push("rand").unwrap();
+        assert_eq!(calc.get_display(), "12.5");
+        assert_eq!(calc.get_history()[0].to_string(), "rand(10, 20) = 12.5");
+    }
+
+    #[test]
+    fn inverse_trig_respects_the_angle_mode() {
+        let mut calc = Calculator::new();
+        calc.push_all(&["0", ".", "5", "asin"]).unwrap();
+        assert_eq!(calc.get_display(), "30");
+
+        calc.push_all(&["ac", "angle", "0", ".", "5", "asin"]).unwrap();
+        assert_eq!(calc.get_display(), "0.5235987756");
+        calc.push_all(&["ac", "1", "atan"]).unwrap();
+        assert_eq!(calc.get_display(), "0.7853981634");
+
+        assert_eq!(calc.push_all(&["ac", "2", "acos"]), Err("Fuera de dominio".to_string()));
+    }
+
+    #[test]
+    fn factorial_of_non_negative_integers() {
+        let mut calc = Calculator::new();
+        calc.push_all(&["0", "fact"]).unwrap();
+        assert_eq!(calc.get_display(), "1");
+        calc.push_all(&["ac", "5", "fact"]).unwrap();
+        assert_eq!(calc.get_history()[1].to_string(), "5! = 120");
+
+        let error = Err("Factorial requiere entero ≥ 0".to_string());
+        assert_eq!(calc.push_all(&["ac", "1", "neg", "fact"]), error);
+        assert_eq!(calc.push_all(&["ac", "3", ".", "5", "fact"]), error);
+        let result = calc.push_all(&["ac", "1", "7", "1", "fact"]);
+        assert_eq!(result, Err("Desbordamiento".to_string()));
+    }
+
+    #[test]
+    fn exponentiation() {
+        let mut calc = Calculator::new();
+        assert_eq!(calc.evaluate_expression("2^10"), Ok(1024.0));
+        assert_eq!(calc.evaluate_expression("2^-1"), Ok(0.5));
+        assert_eq!(calc.evaluate_expression("2^3^2"), Ok(512.0));
+        assert_eq!(calc.evaluate_expression("0^0"), Ok(1.0));
+        assert_eq!(calc.evaluate_expression("-8^0.5"), Err("Resultado complejo".to_string()));
+        assert_eq!(calc.evaluate_expression("10^400"), Err("Desbordamiento".to_string()));
+    }
+
+    #[test]
+    fn modulo_is_euclidean() {
+        let mut calc = Calculator::new();
+        calc.push_all(&["1", "7", "mod", "5", "="]).unwrap();
+        assert_eq!(calc.get_history()[0].to_string(), "17 mod 5 = 2");
+
+        let mut calc = Calculator::new();
+        calc.push_all(&["1", "7", "neg", "mod", "5", "="]).unwrap();
+        assert_eq!(calc.get_display(), "3");
+
+        let mut calc = Calculator::new();
+        let result = calc.push_all(&["1", "7", "mod", "0", "="]);
+        assert_eq!(result, Err("Módulo por cero".to_string()));
+    }
+
+    #[test]
+    fn applies_bitwise_operators_in_integer_mode() {
+        let mut calc = Calculator::new();
+        assert!(calc.push_all(&["1", "2", "and"]).is_err());
+
+        calc.set_integer_mode(true);
+        calc.reset();
+        calc.push_all(&["1", "2", "and", "1", "0", "="]).unwrap();
+        assert_eq!(calc.get_display(), "8");
+        calc.push_all(&["1", "2", "or", "1", "0", "="]).unwrap();
+        assert_eq!(calc.get_display(), "14");
+        calc.push_all(&["1", "2", "xor", "1", "0", "="]).unwrap();
+        assert_eq!(calc.get_display(), "6");
+        assert_eq!(calc.get_history()[2].to_string(), "12 xor 10 = 6");
+
+        calc.push_all(&["1", "+", "2", "and", "3", "="]).unwrap();
+        assert_eq!(calc.get_display(), "3");
+        calc.push_all(&["7", "/", "2", "="]).unwrap();
+        assert_eq!(calc.get_display(), "3");
+    }
+
+    #[test]
+    fn bitwise_operators_reject_fractions() {
+        assert_eq!(apply_operation('&', 1.5, 1.0), Err("Se necesitan números enteros".to_string()));
+    }
+
+    #[test]
+    fn shows_integers_in_hex_and_binary() {
+        let mut calc = Calculator::new();
+        calc.set_integer_mode(true);
+        calc.push_all(&["2", "5", "5", "hex"]).unwrap();
+        assert_eq!(calc.get_display(), "0xff");
+        assert_eq!(calc.current_number, "255");
+
+        calc.push_all(&["ac", "5", "bin"]).unwrap();
+        assert_eq!(calc.get_display(), "0b101");
+        calc.push_all(&["dec"]).unwrap();
+        assert_eq!(calc.get_display(), "5");
+
+        calc.push_all(&["neg", "hex"]).unwrap();
+        assert_eq!(calc.get_display(), "0xfffffffffffffffb");
+    }
+
+    #[test]
+    fn rejects_infinite_and_undefined_results() {
+        let mut calc = Calculator::new();
+        let result = calc.push_all(&["1", "exp_input", "3", "0", "8", "*", "1", "0", "="]);
+        assert_eq!(result, Err("Desbordamiento".to_string()));
+        assert!(calc.get_history().is_empty());
+
+        let mut calc = Calculator::new();
+        let huge = ["1", "exp_input", "4", "0", "0"];
+        calc.push_all(&huge).unwrap();
+        calc.push("-").unwrap();
+        calc.push_all(&huge).unwrap();
+        assert_eq!(calc.push("="), Err("Resultado indefinido".to_string()));
+
+        let mut calc = Calculator::new();
+        calc.push_all(&huge).unwrap();
+        assert_eq!(calc.push("sqr"), Err("Desbordamiento".to_string()));
+    }
+
+    #[test]
+    fn evaluates_pasted_expressions() {
+        let mut calc = Calculator::new();
+        assert_eq!(calc.evaluate_expression("12*(3+4)"), Ok(84.0));
+        assert_eq!(calc.get_history()[0].to_string(), "12 * (3 + 4) = 84");
+        assert_eq!(calc.get_display(), "84");
+        assert_eq!(calc.evaluate_expression("2 * -3"), Ok(-6.0));
+        assert_eq!(calc.evaluate_expression("2 + a"), Err("Carácter no válido: 'a'".to_string()));
+
+        let mut calc = Calculator::new();
+        calc.set_decimal_separator(',');
+        assert_eq!(calc.evaluate_expression("3,5+1"), Ok(4.5));
+        assert_eq!(calc.evaluate_expression("1.000*2"), Ok(2000.0));
+        assert_eq!(calc.get_display(), "2.000");
+    }
+
+    #[test]
+    fn accepts_a_comma_decimal_separator() {
+        let mut dot = Calculator::new();
+        dot.push_all(&["3", ".", "1", "4", "="]).unwrap();
+
+        let mut comma = Calculator::new();
+        comma.set_decimal_separator(',');
+        comma.push_all(&["3", ",", "1", "4"]).unwrap();
+        assert_eq!(comma.get_display(), "3,14");
+        comma.push_all(&["="]).unwrap();
+        assert_eq!(comma.get_history(), dot.get_history());
+
+        comma.push_all(&["ac", "1", "2", "3", "4", ".", "5"]).unwrap();
+        assert_eq!(comma.get_display(), "1.234,5");
+        assert_eq!(Calculator::from_json(&comma.to_json()).unwrap().get_display(), "1,234.5");
+    }
+
+    #[test]
+    fn formats_thousands_separators() {
+        assert_eq!(format_display("1000"), "1,000");
+        assert_eq!(format_display("-12345.67"), "-12,345.67");
+        assert_eq!(format_display("1234."), "1,234.");
+        assert_eq!(format_display("0"), "0");
+    }
+
+    #[test]
+    fn applies_the_locked_constant_to_each_result() {
+        let mut calc = Calculator::new();
+        calc.push_all(&["+", "5", "k"]).unwrap();
+        assert!(calc.has_constant());
+        calc.push_all(&["1", "0", "="]).unwrap();
+        assert_eq!(calc.get_display(), "15");
+        calc.push_all(&["2", "0", "="]).unwrap();
+        assert_eq!(calc.get_display(), "25");
+        // Una expresión completa no usa la constante
+        calc.push_all(&["2", "*", "3", "="]).unwrap();
+        assert_eq!(calc.get_display(), "6");
+        // …pero sigue fijada para el siguiente número: 1 + 5
+        calc.push_all(&["1", "="]).unwrap();
+        assert_eq!(calc.get_display(), "6");
+
+        calc.push("k").unwrap();
+        assert!(!calc.has_constant());
+        calc.push_all(&["7", "="]).unwrap();
+        assert_eq!(calc.get_display(), "7");
+        assert!(calc.push("k").is_err());
+
+        // El operando izquierdo se queda en pantalla y `=` ya le aplica la constante
+        let mut calc = Calculator::new();
+        calc.push_all(&["3", "*", "4", "k"]).unwrap();
+        assert_eq!(calc.get_display(), "3");
+        calc.push_all(&["="]).unwrap();
+        assert_eq!(calc.get_display(), "12");
+
+        // Con paréntesis o más de una operación no hay constante que fijar
+        let mut calc = Calculator::new();
+        assert!(calc.push_all(&["(", "2", "+", "5", "k"]).is_err());
+        assert!(calc.push_all(&["ac", "1", "+", "2", "*", "5", "k"]).is_err());
+        assert!(!calc.has_constant());
+        calc.push_all(&["="]).unwrap();
+        assert_eq!(calc.get_display(), "11");
+    }
+
+    #[test]
+    fn counts_only_successful_evaluations() {
+        let mut calc = Calculator::new();
+        calc.push_all(&["1", "2", "3"]).unwrap();
+        assert_eq!(calc.stats(), (0, 0));
+
+        calc.push_all(&["+", "4", "=", "="]).unwrap();
+        assert_eq!(calc.stats(), (2, 2));
+        calc.push_all(&["/", "0"]).unwrap();
+        assert!(calc.push("=").is_err());
+        assert_eq!(calc.stats(), (2, 2));
+
+        let restored = Calculator::from_json(&calc.to_json()).unwrap();
+        assert_eq!(restored.stats(), (2, 2));
+    }
+
+    #[test]
+    fn formats_results_as_currency() {
+        let mut calc = Calculator::new();
+        calc.set_currency_mode(true);
+        assert_eq!(calc.format_value(1234.5), "$1,234.50");
+        assert_eq!(calc.format_value(-0.5), "-$0.50");
+
+        // Mientras se escribe no se añaden decimales
+        calc.push_all(&["1", "2", "3", "4", ".", "5"]).unwrap();
+        assert_eq!(calc.get_display(), "1,234.5");
+        calc.push_all(&["+", "0", "="]).unwrap();
+        assert_eq!(calc.get_display(), "$1,234.50");
+
+        calc.set_currency_symbol("€");
+        calc.set_decimal_separator(',');
+        assert_eq!(calc.get_display(), "€1.234,50");
+    }
+
+    #[test]
+    fn switches_to_scientific_notation() {
+        let calc = Calculator::new();
+        assert_eq!(calc.format_number(&1e20_f64.to_string()), "1e20");
+        assert_eq!(calc.format_number(&0.0000001_f64.to_string()), "1e-7");
+        assert_eq!(calc.format_number("42"), "42");
+
+        let mut calc = Calculator::new();
+        calc.set_exponent_range(-3, 6);
+        assert_eq!(calc.format_number("1234567"), "1.234567e6");
+        assert_eq!(calc.format_number("0.0001"), "1e-4");
+        assert_eq!(calc.format_number("0.001"), "0.001");
+    }
+
+    #[test]
+    fn rounds_to_the_configured_precision() {
+        let mut calc = Calculator::new();
+        calc.push_all(&["0", ".", "1", "+", "0", ".", "2", "="]).unwrap();
+        assert_eq!(calc.get_display(), "0.3");
+
+        let mut calc = Calculator::new();
+        calc.set_precision(2);
+        calc.push_all(&["2", "/", "3", "="]).unwrap();
+        assert_eq!(calc.get_display(), "0.67");
+    }
+
+    #[test]
+    fn keeps_the_unrounded_result() {
+        let mut calc = Calculator::new();
+        calc.push_all(&["2", "/", "3", "="]).unwrap();
+        assert_eq!(calc.get_display(), "0.6666666667");
+        assert_eq!(calc.full_precision(), Some(String::from("0.6666666666666666")));
+
+        // Un resultado exacto no tiene nada más que mostrar
+        calc.push_all(&["6", "/", "3", "="]).unwrap();
+        assert_eq!(calc.full_precision(), None);
+    }
+
+    #[test]
+    fn rejects_results_above_the_maximum_magnitude() {
+        let mut calc = Calculator::new();
+        calc.set_max_magnitude(Some(1000.0));
+        calc.push_all(&["9", "9", "9", "+", "1", "="]).unwrap();
+        assert_eq!(calc.get_display(), "1,000");
+        assert_eq!(
+            calc.push_all(&["ac", "5", "0", "0", "*", "3", "="]),
+            Err(String::from("Número demasiado grande"))
+        );
+        // El límite vale también para los negativos
+        assert!(calc.push_all(&["ac", "1", "0", "0", "0", "neg", "-", "1", "="]).is_err());
+
+        calc.set_max_magnitude(None);
+        calc.push_all(&["ac", "5", "0", "0", "*", "3", "="]).unwrap();
+        assert_eq!(calc.get_display(), "1,500");
+    }
+
+    #[test]
+    fn rounds_with_the_configured_mode() {
+        let cases = [
+            (RoundingMode::HalfUp, ["3", "-3", "2"]),
+            (RoundingMode::HalfEven, ["2", "-2", "2"]),
+            (RoundingMode::Floor, ["2", "-3", "1"]),
+            (RoundingMode::Ceil, ["3", "-2", "2"]),
+            (RoundingMode::Truncate, ["2", "-2", "1"]),
+        ];
+        for (mode, expected) in cases {
+            let mut calc = Calculator::new();
+            calc.set_precision(0);
+            calc.set_rounding_mode(mode);
+            calc.push_all(&["5", "/", "2", "="]).unwrap();
+            assert_eq!(calc.get_display(), expected[0], "{:?}", mode);
+            calc.push_all(&["0", "-", "5", "/", "2", "="]).unwrap();
+            assert_eq!(calc.get_display(), expected[1], "{:?}", mode);
+            calc.push_all(&["7", "/", "4", "="]).unwrap();
+            assert_eq!(calc.get_display(), expected[2], "{:?}", mode);
+        }
+    }
+
+    #[test]
+    fn undo_and_redo_whole_operations() {
+        let mut calc = Calculator::new();
+        calc.push_all(&["2", "+", "3", "="]).unwrap();
+        calc.push_all(&["undo"]).unwrap();
+        assert_eq!(calc.get_expression(), "2 +");
+        assert_eq!(calc.get_display(), "3");
+        calc.push_all(&["undo", "redo", "redo"]).unwrap();
+        assert_eq!(calc.get_display(), "5");
+
+        calc.max_input_len = MAX_UNDO + 20;
+        for _ in 0..MAX_UNDO + 10 {
+            calc.push("1").unwrap();
+        }
+        assert_eq!(calc.undo_stack.len(), MAX_UNDO);
+    }
+
+    #[test]
+    fn undo_removes_the_history_entry_of_the_undone_result() {
+        let mut calc = Calculator::new();
+        calc.push_all(&["2", "+", "3", "=", "undo"]).unwrap();
+        assert!(calc.get_history().is_empty());
+        assert_eq!(calc.grand_total(), 0.0);
+        assert_eq!(calc.get_display(), "3");
+        // Se sigue escribiendo el 3, no empieza un número nuevo
+        calc.push_all(&["1", "="]).unwrap();
+        assert_eq!(calc.get_display(), "33");
+
+        calc.push_all(&["undo", "redo"]).unwrap();
+        assert_eq!(calc.get_history().len(), 1);
+        assert_eq!(calc.get_history()[0].to_string(), "2 + 31 = 33");
+    }
+}
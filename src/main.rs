@@ -1,184 +1,1359 @@
 use leptos::*;
+use leptos::leptos_dom::helpers::IntervalHandle;
 use leptos::logging::log;
-use web_sys::MouseEvent;
-use std::cell::RefCell;
+use leptos::wasm_bindgen::{JsCast, JsValue};
+use serde::{Deserialize, Serialize};
+use wasm_bindgen_futures::{spawn_local, JsFuture};
+use web_sys::{ClipboardEvent, MouseEvent};
+use std::cell::{Cell, RefCell};
+use std::collections::VecDeque;
 use std::rc::Rc;
+use std::time::Duration;
 
-#[derive(Clone)]
-struct Calculator {
-    display: String,
-    current_number: String,
-    operation: Option<char>,
-    previous_number: Option<f64>,
-    history: Vec<String>,
+mod calculator;
+
+use calculator::{tip_split, AngleMode, Calculator, CopyMode, NumberBase, RoundingMode};
+
+const STORAGE_KEY: &str = "calculadora";
+const SETTINGS_KEY: &str = "calculadora-ajustes";
+
+// Preferencias del panel de ajustes; se guardan juntas bajo `SETTINGS_KEY`
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+struct Settings {
+    precision: usize,
+    rounding_mode: RoundingMode,
+    angle_mode: AngleMode,
+    theme: String,
+    decimal_separator: char,
+    tax_rate: f64,
+    discount_rate: f64,
+    max_history: usize,
+    random_min: f64,
+    random_max: f64,
+    min_exponent: i32,
+    max_exponent: i32,
+    sound_enabled: bool,
+    auto_clear_on_error: bool,
+    copy_mode: CopyMode,
+    max_magnitude: Option<f64>,
+    currency_mode: bool,
+    currency_symbol: String,
 }
 
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            precision: 10,
+            rounding_mode: RoundingMode::HalfUp,
+            angle_mode: AngleMode::Degrees,
+            theme: String::from("light"),
+            decimal_separator: '.',
+            tax_rate: 16.0,
+            discount_rate: 10.0,
+            max_history: 100,
+            random_min: 0.0,
+            random_max: 1.0,
+            min_exponent: -6,
+            max_exponent: 15,
+            sound_enabled: false,
+            auto_clear_on_error: false,
+            copy_mode: CopyMode::FullExpression,
+            max_magnitude: None,
+            currency_mode: false,
+            currency_symbol: String::from("$"),
+        }
+    }
+}
+
+fn local_storage() -> Option<web_sys::Storage> {
+    web_sys::window()?.local_storage().ok()?
+}
 
+// Si no hay nada guardado o el JSON está corrupto se empieza de cero
+fn load_calculator() -> Calculator {
+    local_storage()
+        .and_then(|storage| storage.get_item(STORAGE_KEY).ok()?)
+        .and_then(|json| Calculator::from_json(&json).ok())
+        .unwrap_or_else(Calculator::new)
+}
+
+fn save_calculator(calc: &Calculator) {
+    if let Some(storage) = local_storage() {
+        let _ = storage.set_item(STORAGE_KEY, &calc.to_json());
+    }
+}
+
+fn load_settings() -> Settings {
+    local_storage()
+        .and_then(|storage| storage.get_item(SETTINGS_KEY).ok()?)
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+fn save_settings(settings: &Settings) {
+    if let (Some(storage), Ok(json)) = (local_storage(), serde_json::to_string(settings)) {
+        let _ = storage.set_item(SETTINGS_KEY, &json);
+    }
+}
 
-impl Calculator {
-    fn new() -> Self {
-        Calculator {
-            display: String::from("0"),
-            current_number: String::new(),
-            operation: None,
-            previous_number: None,
-            history: Vec::new(),
+fn apply_settings(calc: &mut Calculator, settings: &Settings) {
+    calc.set_precision(settings.precision);
+    calc.set_rounding_mode(settings.rounding_mode);
+    calc.set_angle_mode(settings.angle_mode);
+    calc.set_decimal_separator(settings.decimal_separator);
+    calc.set_tax_rate(settings.tax_rate);
+    calc.set_discount_rate(settings.discount_rate);
+    calc.set_max_history(settings.max_history);
+    calc.set_random_range(settings.random_min, settings.random_max);
+    calc.set_exponent_range(settings.min_exponent, settings.max_exponent);
+    calc.set_auto_clear_on_error(settings.auto_clear_on_error);
+    calc.set_max_magnitude(settings.max_magnitude);
+    calc.set_currency_mode(settings.currency_mode);
+    calc.set_currency_symbol(&settings.currency_symbol);
+}
+
+async fn copy_to_clipboard(text: &str) -> Result<(), JsValue> {
+    let window = web_sys::window().ok_or(JsValue::NULL)?;
+    JsFuture::from(window.navigator().clipboard().write_text(text)).await?;
+    Ok(())
+}
+
+async fn read_clipboard() -> Result<String, JsValue> {
+    let window = web_sys::window().ok_or(JsValue::NULL)?;
+    let text = JsFuture::from(window.navigator().clipboard().read_text()).await?;
+    text.as_string().ok_or(JsValue::NULL)
+}
+
+// Descarga `contents` como un fichero mediante un Blob y un enlace temporal
+fn download_file(filename: &str, mime: &str, contents: &str) -> Result<(), JsValue> {
+    let options = web_sys::BlobPropertyBag::new();
+    options.set_type(mime);
+    let parts = js_sys::Array::of1(&JsValue::from_str(contents));
+    let blob = web_sys::Blob::new_with_str_sequence_and_options(&parts, &options)?;
+    let url = web_sys::Url::create_object_url_with_blob(&blob)?;
+
+    let link = document()
+        .create_element("a")?
+        .dyn_into::<web_sys::HtmlAnchorElement>()?;
+    link.set_href(&url);
+    link.set_download(filename);
+    link.click();
+    web_sys::Url::revoke_object_url(&url)
+}
+
+// Pitido corto: un oscilador que se apaga solo a los 40 ms
+fn play_click(context: &web_sys::AudioContext) -> Result<(), JsValue> {
+    let oscillator = context.create_oscillator()?;
+    let gain = context.create_gain()?;
+    oscillator.set_type(web_sys::OscillatorType::Square);
+    oscillator.frequency().set_value(880.0);
+    gain.gain().set_value(0.05);
+    oscillator.connect_with_audio_node(&gain)?;
+    gain.connect_with_audio_node(&context.destination())?;
+    oscillator.start()?;
+    oscillator.stop_with_when(context.current_time() + 0.04)
+}
+
+async fn read_file(file: web_sys::File) -> Result<String, JsValue> {
+    let text = JsFuture::from(file.text()).await?;
+    text.as_string().ok_or(JsValue::NULL)
+}
+
+// Hora local de una entrada del historial; las importadas sin hora muestran un guion
+fn format_timestamp(timestamp: Option<f64>) -> String {
+    match timestamp {
+        Some(millis) => {
+            let date = js_sys::Date::new(&JsValue::from_f64(millis));
+            format!("{:02}:{:02}:{:02}", date.get_hours(), date.get_minutes(), date.get_seconds())
         }
+        None => String::from("—"),
     }
+}
 
-    fn push(&mut self, value: &str) -> Result<(), String> {
-        match value {
-            "+" | "-" | "*" | "/" => {
-                if !self.current_number.is_empty() {
-                    self.operation = Some(value.chars().next().unwrap());
-                    self.previous_number = Some(self.current_number.parse().unwrap());
-                    self.current_number.clear();
-                }
-            }
-            "=" => {
-                if let (Some(prev), Some(op)) = (self.previous_number, self.operation) {
-                    if let Ok(current) = self.current_number.parse::<f64>() {
-                        let result = match op {
-                            '+' => prev + current,
-                            '-' => prev - current,
-                            '*' => prev * current,
-                            '/' => {
-                                if current == 0.0 {
-                                    return Err("División por cero".to_string());
-                                }
-                                prev / current
-                            }
-                            _ => return Err("Operación inválida".to_string()),
-                        };
-                        
-                        let operation = format!("{} {} {} = {}", prev, op, current, result);
-                        self.history.push(operation);
-                        
-                        self.current_number = result.to_string();
-                        self.previous_number = None;
-                        self.operation = None;
-                    }
-                }
-            }
-            "ac" => self.reset(),
-            "<" => self.undo(),
-            _ => self.current_number.push_str(value),
+// Teclas físicas y el comando de la calculadora que disparan
+const KEY_MAP: &[(&str, &str)] = &[
+    ("0", "0"),
+    ("1", "1"),
+    ("2", "2"),
+    ("3", "3"),
+    ("4", "4"),
+    ("5", "5"),
+    ("6", "6"),
+    ("7", "7"),
+    ("8", "8"),
+    ("9", "9"),
+    ("+", "+"),
+    ("-", "-"),
+    ("*", "*"),
+    ("/", "/"),
+    ("^", "^"),
+    (".", "."),
+    (",", "."),
+    ("(", "("),
+    (")", ")"),
+    ("Enter", "="),
+    ("=", "="),
+    ("Backspace", "<"),
+    ("Escape", "ac"),
+    ("Delete", "ce"),
+    ("c", "ce"),
+    ("C", "ce"),
+];
+
+fn key_to_command(key: &str) -> Option<&'static str> {
+    KEY_MAP
+        .iter()
+        .find(|(mapped, _)| *mapped == key)
+        .map(|(_, command)| *command)
+}
+
+// Reduce la letra de la pantalla a medida que el número se alarga
+fn display_font_size(length: usize) -> &'static str {
+    match length {
+        0..=11 => "2em",
+        12..=15 => "1.5em",
+        16..=20 => "1.2em",
+        _ => "0.9em",
+    }
+}
+
+// Distancia mínima en píxeles para que un deslizamiento cuente como gesto
+const SWIPE_MIN_DISTANCE: i32 = 50;
+
+// Deslizar hacia la izquierda, más en horizontal que en vertical, borra un dígito
+fn is_swipe_left(dx: i32, dy: i32) -> bool {
+    dx <= -SWIPE_MIN_DISTANCE && dx.abs() > dy.abs()
+}
+
+// Columnas de la rejilla del teclado; los botones `wide` ocupan dos
+const KEYPAD_COLUMNS: usize = 4;
+
+// Botón al que pasa el foco con una flecha. Los botones se colocan como en la
+// rejilla CSS; arriba y abajo buscan el de la fila vecina que cubre la misma columna.
+fn keypad_neighbor(spans: &[usize], index: usize, key: &str) -> usize {
+    let mut cells = Vec::with_capacity(spans.len());
+    let (mut row, mut column) = (0, 0);
+    for &span in spans {
+        if column + span > KEYPAD_COLUMNS {
+            row += 1;
+            column = 0;
         }
-        
-        self.update_display();
-        Ok(())
+        cells.push((row, column, span));
+        column += span;
+    }
+    let Some(&(row, column, _)) = cells.get(index) else {
+        return index;
+    };
+    let covering = |target: usize| {
+        cells
+            .iter()
+            .position(|&(r, c, span)| r == target && c <= column && column < c + span)
+    };
+    match key {
+        "ArrowLeft" => index.saturating_sub(1),
+        "ArrowRight" => (index + 1).min(spans.len() - 1),
+        "ArrowUp" => row.checked_sub(1).and_then(covering).unwrap_or(index),
+        "ArrowDown" => covering(row + 1).unwrap_or(index),
+        _ => index,
     }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Layout {
+    Standard,
+    Scientific,
+    Programmer,
+    // Solo dígitos y un botón para sumar al total, para meter datos deprisa
+    Quick,
+}
 
-    fn reset(&mut self) {
-        self.display = String::from("0");
-        self.current_number.clear();
-        self.operation = None;
-        self.previous_number = None;
+impl Layout {
+    // El botón de distribución las recorre en orden
+    fn next(self) -> Layout {
+        match self {
+            Layout::Standard => Layout::Scientific,
+            Layout::Scientific => Layout::Programmer,
+            Layout::Programmer => Layout::Quick,
+            Layout::Quick => Layout::Standard,
+        }
     }
 
-    fn undo(&mut self) {
-        if !self.current_number.is_empty() {
-            self.current_number.pop();
-            self.update_display();
+    fn label(self) -> &'static str {
+        match self {
+            Layout::Standard => "Estándar",
+            Layout::Scientific => "Científica",
+            Layout::Programmer => "Prog",
+            Layout::Quick => "Rápida",
         }
     }
+}
 
-    fn update_display(&mut self) {
-        self.display = if self.current_number.is_empty() {
-            String::from("0")
-        } else {
-            self.current_number.clone()
-        };
+// Teclas que solo aparecen en la distribución científica
+fn scientific_keypad(
+    on_clicked: impl Fn(MouseEvent) + Clone + 'static,
+    last_pressed: ReadSignal<String>,
+) -> impl IntoView {
+    view! {
+        <div class="keypad scientific">
+            <button on:click=on_clicked.clone() value="sqrt" class:pressed=move || last_pressed.get() == "sqrt" aria-label="raíz cuadrada">"√"</button>
+            <button on:click=on_clicked.clone() value="sqr" class:pressed=move || last_pressed.get() == "sqr" aria-label="cuadrado">"x²"</button>
+            <button on:click=on_clicked.clone() value="^" class:pressed=move || last_pressed.get() == "^" aria-label="potencia">"xʸ"</button>
+            <button on:click=on_clicked.clone() value="recip" class:pressed=move || last_pressed.get() == "recip" aria-label="inverso">"1/x"</button>
+
+            <button on:click=on_clicked.clone() value="sin" class:pressed=move || last_pressed.get() == "sin" aria-label="seno">"sin"</button>
+            <button on:click=on_clicked.clone() value="cos" class:pressed=move || last_pressed.get() == "cos" aria-label="coseno">"cos"</button>
+            <button on:click=on_clicked.clone() value="tan" class:pressed=move || last_pressed.get() == "tan" aria-label="tangente">"tan"</button>
+            <button on:click=on_clicked.clone() value="mod" class:pressed=move || last_pressed.get() == "mod" aria-label="módulo">"mod"</button>
+
+            <button on:click=on_clicked.clone() value="asin" class:pressed=move || last_pressed.get() == "asin" aria-label="arcoseno">"sin⁻¹"</button>
+            <button on:click=on_clicked.clone() value="acos" class:pressed=move || last_pressed.get() == "acos" aria-label="arcocoseno">"cos⁻¹"</button>
+            <button on:click=on_clicked.clone() value="atan" class:pressed=move || last_pressed.get() == "atan" aria-label="arcotangente" class="wide">"tan⁻¹"</button>
+
+            <button on:click=on_clicked.clone() value="log" class:pressed=move || last_pressed.get() == "log" aria-label="logaritmo decimal">"log"</button>
+            <button on:click=on_clicked.clone() value="ln" class:pressed=move || last_pressed.get() == "ln" aria-label="logaritmo natural">"ln"</button>
+            <button on:click=on_clicked.clone() value="exp" class:pressed=move || last_pressed.get() == "exp" aria-label="exponencial" class="wide">"eˣ"</button>
+
+            <button on:click=on_clicked.clone() value="pi" class:pressed=move || last_pressed.get() == "pi" aria-label="pi">"π"</button>
+            <button on:click=on_clicked.clone() value="e" class:pressed=move || last_pressed.get() == "e" aria-label="número e">"e"</button>
+            <button on:click=on_clicked.clone() value="fact" class:pressed=move || last_pressed.get() == "fact" aria-label="factorial">"n!"</button>
+            <button on:click=on_clicked.clone() value="exp_input" class:pressed=move || last_pressed.get() == "exp_input" aria-label="exponente científico">"EE"</button>
+
+            <button on:click=on_clicked.clone() value="dms_deg" class:pressed=move || last_pressed.get() == "dms_deg" aria-label="grados">"°"</button>
+            <button on:click=on_clicked.clone() value="dms_min" class:pressed=move || last_pressed.get() == "dms_min" aria-label="minutos">"′"</button>
+            <button on:click=on_clicked.clone() value="dms_sec" class:pressed=move || last_pressed.get() == "dms_sec" aria-label="segundos">"″"</button>
+            <button on:click=on_clicked.clone() value="dms" class:pressed=move || last_pressed.get() == "dms" aria-label="grados, minutos y segundos">"DMS"</button>
+
+            <button on:click=on_clicked.clone() value="abs" class:pressed=move || last_pressed.get() == "abs" aria-label="valor absoluto" class="wide">"|x|"</button>
+            <button on:click=on_clicked.clone() value="pct" class:pressed=move || last_pressed.get() == "pct" aria-label="entre cien" class="wide">"x/100"</button>
+
+            <button on:click=on_clicked.clone() value="rand" class:pressed=move || last_pressed.get() == "rand" aria-label="número aleatorio" class="wide">"Ran#"</button>
+            <button on:click=on_clicked.clone() value="frac" class:pressed=move || last_pressed.get() == "frac" aria-label="fracción o decimal" class="wide">"a/b"</button>
+
+            <button on:click=on_clicked.clone() value="data" class:pressed=move || last_pressed.get() == "data" aria-label="añadir dato">"DATA"</button>
+            <button on:click=on_clicked.clone() value="mean" class:pressed=move || last_pressed.get() == "mean" aria-label="media">"x̄"</button>
+            <button on:click=on_clicked.clone() value="sum" class:pressed=move || last_pressed.get() == "sum" aria-label="suma de datos">"Σx"</button>
+            <button on:click=on_clicked.clone() value="count" class:pressed=move || last_pressed.get() == "count" aria-label="número de datos">"n"</button>
+            <button on:click=on_clicked.clone() value="data_clear" class:pressed=move || last_pressed.get() == "data_clear" aria-label="borrar datos" class="wide">"CLR DATA"</button>
+        </div>
     }
+}
+
+// Operaciones de bits del modo programador
+fn programmer_keypad(
+    on_clicked: impl Fn(MouseEvent) + Clone + 'static,
+    base: Memo<NumberBase>,
+    last_pressed: ReadSignal<String>,
+) -> impl IntoView {
+    view! {
+        <div class="keypad programmer">
+            <button on:click=on_clicked.clone() value="dec" class:pressed=move || last_pressed.get() == "dec" aria-label="decimal" class:active=move || base.get() == NumberBase::Decimal>"DEC"</button>
+            <button on:click=on_clicked.clone() value="hex" class:pressed=move || last_pressed.get() == "hex" aria-label="hexadecimal" class:active=move || base.get() == NumberBase::Hexadecimal>"HEX"</button>
+            <button on:click=on_clicked.clone() value="bin" class:pressed=move || last_pressed.get() == "bin" aria-label="binario" class:active=move || base.get() == NumberBase::Binary class="wide">"BIN"</button>
 
-    fn get_display(&self) -> String {
-        self.display.clone()
+            <button on:click=on_clicked.clone() value="and" class:pressed=move || last_pressed.get() == "and" aria-label="y bit a bit">"AND"</button>
+            <button on:click=on_clicked.clone() value="or" class:pressed=move || last_pressed.get() == "or" aria-label="o bit a bit">"OR"</button>
+            <button on:click=on_clicked.clone() value="xor" class:pressed=move || last_pressed.get() == "xor" aria-label="o exclusivo bit a bit" class="wide">"XOR"</button>
+        </div>
     }
+}
+
+// Teclado del modo rápido: cada número se suma al total con una pulsación
+fn quick_keypad(
+    on_clicked: impl Fn(MouseEvent) + Clone + 'static,
+    decimal_separator: ReadSignal<char>,
+    last_pressed: ReadSignal<String>,
+) -> impl IntoView {
+    view! {
+        <div class="keypad quick">
+            <button on:click=on_clicked.clone() value="7" class:pressed=move || last_pressed.get() == "7" aria-label="7">"7"</button>
+            <button on:click=on_clicked.clone() value="8" class:pressed=move || last_pressed.get() == "8" aria-label="8">"8"</button>
+            <button on:click=on_clicked.clone() value="9" class:pressed=move || last_pressed.get() == "9" aria-label="9">"9"</button>
+            <button on:click=on_clicked.clone() value="<" class:pressed=move || last_pressed.get() == "<" aria-label="borrar último dígito">"⬅"</button>
+
+            <button on:click=on_clicked.clone() value="4" class:pressed=move || last_pressed.get() == "4" aria-label="4">"4"</button>
+            <button on:click=on_clicked.clone() value="5" class:pressed=move || last_pressed.get() == "5" aria-label="5">"5"</button>
+            <button on:click=on_clicked.clone() value="6" class:pressed=move || last_pressed.get() == "6" aria-label="6">"6"</button>
+            <button on:click=on_clicked.clone() value="ce" class:pressed=move || last_pressed.get() == "ce" aria-label="borrar entrada">"CE"</button>
+
+            <button on:click=on_clicked.clone() value="1" class:pressed=move || last_pressed.get() == "1" aria-label="1">"1"</button>
+            <button on:click=on_clicked.clone() value="2" class:pressed=move || last_pressed.get() == "2" aria-label="2">"2"</button>
+            <button on:click=on_clicked.clone() value="3" class:pressed=move || last_pressed.get() == "3" aria-label="3">"3"</button>
+            <button on:click=on_clicked.clone() value="." class:pressed=move || last_pressed.get() == "." aria-label="separador decimal">{move || decimal_separator.get().to_string()}</button>
 
-    fn get_history(&self) -> Vec<String> {
-        self.history.clone()
+            <button on:click=on_clicked.clone() value="0" class:pressed=move || last_pressed.get() == "0" aria-label="0" class="wide">"0"</button>
+            <button on:click=on_clicked.clone() value="total_reset" class:pressed=move || last_pressed.get() == "total_reset" aria-label="reiniciar total" class="wide">"Reiniciar"</button>
+
+            <button on:click=on_clicked.clone() value="total_add" class:pressed=move || last_pressed.get() == "total_add" aria-label="sumar al total" class="total-add">"+ Total"</button>
+        </div>
+    }
+}
+
+// Pantalla principal; `children` va delante del valor (indicadores y botones)
+#[component]
+fn Display(#[prop(into)] value: Signal<String>, #[prop(optional)] children: Option<Children>) -> impl IntoView {
+    view! {
+        <div
+            class="display"
+            aria-live="polite"
+            style=move || format!("font-size: {}", display_font_size(value.with(|value| value.chars().count())))
+        >
+            {children.map(|children| children())}
+            {value}
+        </div>
+    }
+}
+
+// Teclado básico. Cada pulsación llega como el comando de la tecla a `on_input`,
+// así que se puede montar por separado, sin historial ni ajustes.
+#[component]
+fn Keypad(
+    on_input: Callback<String>,
+    // Tecla que se resalta como pulsada
+    #[prop(into, optional)] pressed: Option<Signal<String>>,
+    #[prop(into, optional)] decimal_separator: Option<Signal<char>>,
+    // `=` se desactiva cuando el resultado sería seguro un error
+    #[prop(into, optional)] equals_disabled: Option<Signal<bool>>,
+) -> impl IntoView {
+    let pressed = pressed.unwrap_or_else(|| Signal::derive(String::new));
+    let decimal_separator = decimal_separator.unwrap_or_else(|| Signal::derive(|| '.'));
+    let equals_disabled = equals_disabled.unwrap_or_else(|| Signal::derive(|| false));
+    let on_click = move |ev: MouseEvent| on_input.call(event_target_value(&ev));
+
+    // Foco itinerante: solo el botón activo del teclado entra en el orden de
+    // tabulación y las flechas lo mueven por la rejilla
+    let keypad_ref = create_node_ref::<html::Div>();
+    let (focused_key, set_focused_key) = create_signal(0_usize);
+    let keypad_buttons = move || -> Vec<web_sys::HtmlElement> {
+        let Some(keypad) = keypad_ref.get_untracked() else {
+            return Vec::new();
+        };
+        let children = keypad.children();
+        (0..children.length())
+            .filter_map(|index| children.item(index)?.dyn_into::<web_sys::HtmlElement>().ok())
+            .collect()
+    };
+    create_effect(move |_| {
+        // Se repite cuando el teclado llega a montarse
+        if keypad_ref.get().is_none() {
+            return;
+        }
+        let focused = focused_key.get();
+        for (index, button) in keypad_buttons().iter().enumerate() {
+            let _ = button.set_attribute("tabindex", if index == focused { "0" } else { "-1" });
+        }
+    });
+    let on_keypad_focus = move |ev: ev::FocusEvent| {
+        let target = ev.target().and_then(|target| target.dyn_into::<web_sys::HtmlElement>().ok());
+        if let Some(index) = keypad_buttons().iter().position(|button| Some(button) == target.as_ref()) {
+            set_focused_key.set(index);
+        }
+    };
+    let on_keypad_keydown = move |ev: ev::KeyboardEvent| {
+        let buttons = keypad_buttons();
+        let Some(current) = buttons.get(focused_key.get_untracked()) else {
+            return;
+        };
+        let key = ev.key();
+        // No llegan al atajo global: Enter pulsa el botón en vez de hacer `=`
+        if key == "Enter" {
+            ev.prevent_default();
+            ev.stop_propagation();
+            current.click();
+        } else if key.starts_with("Arrow") {
+            ev.prevent_default();
+            ev.stop_propagation();
+            let spans: Vec<usize> = buttons
+                .iter()
+                .map(|button| if button.class_name().split_whitespace().any(|c| c == "wide") { 2 } else { 1 })
+                .collect();
+            let next = keypad_neighbor(&spans, focused_key.get_untracked(), &key);
+            set_focused_key.set(next);
+            let _ = buttons[next].focus();
+        }
+    };
+
+    view! {
+        <div class="keypad" node_ref=keypad_ref on:focusin=on_keypad_focus on:keydown=on_keypad_keydown>
+            <button on:click=on_click value="mc" class:pressed=move || pressed.get() == "mc" aria-label="borrar memoria">"MC"</button>
+            <button on:click=on_click value="mr" class:pressed=move || pressed.get() == "mr" aria-label="recuperar memoria">"MR"</button>
+            <button on:click=on_click value="m-" class:pressed=move || pressed.get() == "m-" aria-label="restar de memoria">"M-"</button>
+            <button on:click=on_click value="m+" class:pressed=move || pressed.get() == "m+" aria-label="sumar a memoria">"M+"</button>
+
+            <button on:click=on_click value="gt" class:pressed=move || pressed.get() == "gt" aria-label="gran total">"GT"</button>
+            <button on:click=on_click value="gt_clear" class:pressed=move || pressed.get() == "gt_clear" aria-label="borrar gran total">"GT C"</button>
+            <button on:click=on_click value="ans" class:pressed=move || pressed.get() == "ans" aria-label="resultado anterior">"ANS"</button>
+            <button on:click=on_click value="k" class:pressed=move || pressed.get() == "k" aria-label="fijar operación constante">"K"</button>
+
+            <button on:click=on_click value="tax+" class:pressed=move || pressed.get() == "tax+" aria-label="sumar impuesto" class="wide">"+IVA"</button>
+            <button on:click=on_click value="tax-" class:pressed=move || pressed.get() == "tax-" aria-label="quitar impuesto" class="wide">"-IVA"</button>
+
+            <button on:click=on_click value="discount" class:pressed=move || pressed.get() == "discount" aria-label="aplicar descuento">"-Dto"</button>
+            <button on:click=on_click value="markup" class:pressed=move || pressed.get() == "markup" aria-label="aplicar margen">"+Mrg"</button>
+            <button on:click=on_click value="original_price" class:pressed=move || pressed.get() == "original_price" aria-label="precio sin descuento" class="wide">"Precio orig."</button>
+
+            // Extras
+            <button on:click=on_click value="pct_change" class:pressed=move || pressed.get() == "pct_change" aria-label="variación porcentual" class="wide">"Δ%"</button>
+            <button on:click=on_click value="ratio" class:pressed=move || pressed.get() == "ratio" aria-label="proporción" class="wide">"a:b"</button>
+
+            <button on:click=on_click value="(" class:pressed=move || pressed.get() == "(" aria-label="abrir paréntesis">"("</button>
+            <button on:click=on_click value=")" class:pressed=move || pressed.get() == ")" aria-label="cerrar paréntesis">")"</button>
+            <button on:click=on_click value="neg" class:pressed=move || pressed.get() == "neg" aria-label="cambiar signo">"±"</button>
+            <button on:click=on_click value="%" class:pressed=move || pressed.get() == "%" aria-label="porcentaje">"%"</button>
+
+            <button on:click=on_click value="7" class:pressed=move || pressed.get() == "7" aria-label="7">"7"</button>
+            <button on:click=on_click value="8" class:pressed=move || pressed.get() == "8" aria-label="8">"8"</button>
+            <button on:click=on_click value="9" class:pressed=move || pressed.get() == "9" aria-label="9">"9"</button>
+            <button on:click=on_click value="/" class:pressed=move || pressed.get() == "/" aria-label="dividir">"÷"</button>
+
+            <button on:click=on_click value="4" class:pressed=move || pressed.get() == "4" aria-label="4">"4"</button>
+            <button on:click=on_click value="5" class:pressed=move || pressed.get() == "5" aria-label="5">"5"</button>
+            <button on:click=on_click value="6" class:pressed=move || pressed.get() == "6" aria-label="6">"6"</button>
+            <button on:click=on_click value="*" class:pressed=move || pressed.get() == "*" aria-label="multiplicar">"×"</button>
+
+            <button on:click=on_click value="1" class:pressed=move || pressed.get() == "1" aria-label="1">"1"</button>
+            <button on:click=on_click value="2" class:pressed=move || pressed.get() == "2" aria-label="2">"2"</button>
+            <button on:click=on_click value="3" class:pressed=move || pressed.get() == "3" aria-label="3">"3"</button>
+            <button on:click=on_click value="-" class:pressed=move || pressed.get() == "-" aria-label="restar">"-"</button>
+
+            <button on:click=on_click value="0" class:pressed=move || pressed.get() == "0" aria-label="0">"0"</button>
+            <button on:click=on_click value="." class:pressed=move || pressed.get() == "." aria-label="separador decimal">{move || decimal_separator.get().to_string()}</button>
+            <button on:click=on_click value="=" class:pressed=move || pressed.get() == "=" disabled=move || equals_disabled.get() aria-label="igual">"="</button>
+            <button on:click=on_click value="+" class:pressed=move || pressed.get() == "+" aria-label="sumar">"+"</button>
+        </div>
     }
 }
 
 #[component]
 fn App() -> impl IntoView {
-    let calculator = Rc::new(RefCell::new(Calculator::new()));
-    let (display, set_display) = create_signal(String::from("0"));
-    let (history, set_history) = create_signal(Vec::<String>::new());
+    let settings = load_settings();
+    let mut initial = load_calculator();
+    apply_settings(&mut initial, &settings);
+    // Única fuente de verdad: lo que se muestra se deriva de la calculadora, y
+    // la señal solo la presta mutable mientras dura cada `update`
+    let calculator = create_rw_signal(initial);
+    let display = create_memo(move |_| calculator.with(Calculator::get_display));
+    let full_precision = create_memo(move |_| calculator.with(Calculator::full_precision));
+    let expression = create_memo(move |_| calculator.with(Calculator::get_expression));
+    let expression_error = create_memo(move |_| calculator.with(|calc| calc.validate_expression().err()));
+    let history = create_memo(move |_| calculator.with(Calculator::get_history));
+    let history_sum = create_memo(move |_| calculator.with(|calc| calc.format_value(calc.history_sum())));
+    let tape = create_memo(move |_| calculator.with(Calculator::get_tape));
+    let stats = create_memo(move |_| calculator.with(Calculator::stats));
+    let memory = create_memo(move |_| calculator.with(Calculator::has_memory));
+    let constant = create_memo(move |_| calculator.with(Calculator::has_constant));
+    let angle_mode = create_memo(move |_| calculator.with(Calculator::angle_mode));
+    let base = create_memo(move |_| calculator.with(Calculator::base));
+    let grand_total = create_memo(move |_| calculator.with(|calc| calc.format_value(calc.grand_total())));
+    let running_total = create_memo(move |_| calculator.with(|calc| calc.format_value(calc.running_total())));
+    let data_count = create_memo(move |_| calculator.with(Calculator::data_count));
+    let divides_by_zero = create_memo(move |_| calculator.with(Calculator::divides_by_zero));
+    let (input_limited, set_input_limited) = create_signal(false);
+    let (error, set_error) = create_signal(String::new());
+    let (copied, set_copied) = create_signal(false);
+    // Vista previa de un resultado del historial; no toca la calculadora
+    let (preview, set_preview) = create_signal(None::<String>);
+    // Al pulsar la pantalla se ve el resultado sin redondear hasta que cambia
+    let (show_full, set_show_full) = create_signal(false);
+    create_effect(move |_| {
+        display.track();
+        set_show_full.set(false);
+    });
+    let shown_display = move || {
+        preview
+            .get()
+            .or_else(|| full_precision.get().filter(|_| show_full.get()))
+            .unwrap_or_else(|| display.get())
+    };
+    // Fila del historial que muestra el aviso de copiado
+    let (copied_entry, set_copied_entry) = create_signal(None::<usize>);
+    let (copied_latex, set_copied_latex) = create_signal(None::<usize>);
+    let (theme, set_theme) = create_signal(settings.theme);
+    let (precision, set_precision) = create_signal(settings.precision);
+    let (rounding_mode, set_rounding_mode) = create_signal(settings.rounding_mode);
+    let (tax_rate, set_tax_rate) = create_signal(settings.tax_rate);
+    let (discount_rate, set_discount_rate) = create_signal(settings.discount_rate);
+    let (max_history, set_max_history) = create_signal(settings.max_history);
+    let (random_min, set_random_min) = create_signal(settings.random_min);
+    let (random_max, set_random_max) = create_signal(settings.random_max);
+    let (min_exponent, set_min_exponent) = create_signal(settings.min_exponent);
+    let (max_exponent, set_max_exponent) = create_signal(settings.max_exponent);
+    let (sound_enabled, set_sound_enabled) = create_signal(settings.sound_enabled);
+    let (auto_clear_on_error, set_auto_clear_on_error) = create_signal(settings.auto_clear_on_error);
+    let (copy_mode, set_copy_mode) = create_signal(settings.copy_mode);
+    let (max_magnitude, set_max_magnitude) = create_signal(settings.max_magnitude);
+    let (currency_mode, set_currency_mode) = create_signal(settings.currency_mode);
+    let (currency_symbol, set_currency_symbol) = create_signal(settings.currency_symbol.clone());
+    let (settings_open, set_settings_open) = create_signal(false);
+    // Panel de propinas: cuenta, porcentaje y número de personas
+    let (tip_open, set_tip_open) = create_signal(false);
+    let (tip_bill, set_tip_bill) = create_signal(0.0);
+    let (tip_percent, set_tip_percent) = create_signal(10.0);
+    let (tip_people, set_tip_people) = create_signal(1_usize);
+    let tip_per_person = move || {
+        match tip_split(tip_bill.get(), tip_percent.get(), tip_people.get()) {
+            Ok(total) => calculator.with(|calc| calc.format_value(total)),
+            Err(msg) => msg,
+        }
+    };
+    let (layout, set_layout) = create_signal(Layout::Standard);
+    let (last_pressed, set_last_pressed) = create_signal(String::new());
+    let (decimal_separator, set_decimal_separator) = create_signal(settings.decimal_separator);
+
+    // Cualquier cambio en los ajustes se guarda de inmediato
+    create_effect(move |_| {
+        save_settings(&Settings {
+            precision: precision.get(),
+            rounding_mode: rounding_mode.get(),
+            angle_mode: angle_mode.get(),
+            theme: theme.get(),
+            decimal_separator: decimal_separator.get(),
+            tax_rate: tax_rate.get(),
+            discount_rate: discount_rate.get(),
+            max_history: max_history.get(),
+            random_min: random_min.get(),
+            random_max: random_max.get(),
+            min_exponent: min_exponent.get(),
+            max_exponent: max_exponent.get(),
+            sound_enabled: sound_enabled.get(),
+            auto_clear_on_error: auto_clear_on_error.get(),
+            copy_mode: copy_mode.get(),
+            max_magnitude: max_magnitude.get(),
+            currency_mode: currency_mode.get(),
+            currency_symbol: currency_symbol.get(),
+        });
+    });
     
-    let calculator_clone = calculator.clone();
-    let on_clicked = move |ev: MouseEvent| {
-        let value = event_target_value(&ev);
+    // La sesión se guarda tras cada cambio de la calculadora
+    create_effect(move |_| calculator.with(save_calculator));
+
+    // Muestra el resultado de una operación: su error o el aviso de límite
+    let report = move |outcome: Result<(), String>| {
+        if calculator.with_untracked(Calculator::hit_input_limit) {
+            set_input_limited.set(true);
+            set_timeout(move || set_input_limited.set(false), Duration::from_millis(800));
+        }
+        set_error.set(outcome.err().unwrap_or_default());
+    };
+
+    // Las entradas se encolan y se procesan de una en una: si llega otra mientras
+    // se actualizan las vistas, se atiende al terminar la actual
+    let pending = Rc::new(RefCell::new(VecDeque::<String>::new()));
+    let processing = Rc::new(Cell::new(false));
+    let process_input = Rc::new(move |value: &str| {
+        pending.borrow_mut().push_back(value.to_string());
+        if processing.replace(true) {
+            return;
+        }
+        loop {
+            // El `RefMut` de la cola no debe vivir durante el cuerpo del bucle
+            let next = pending.borrow_mut().pop_front();
+            let Some(next) = next else {
+                break;
+            };
+            let outcome = calculator.try_update(|calc| calc.push(&next)).unwrap_or(Ok(()));
+            report(outcome);
+        }
+        processing.set(false);
+    });
+
+    // Resalta brevemente la tecla pulsada, con el ratón o con el teclado
+    let flash_key = move |value: &str| {
+        let value = value.to_string();
+        set_last_pressed.set(value.clone());
+        set_timeout(
+            move || {
+                if last_pressed.get_untracked() == value {
+                    set_last_pressed.set(String::new());
+                }
+            },
+            Duration::from_millis(150),
+        );
+    };
+
+    // El contexto de audio se crea con la primera pulsación (los navegadores
+    // lo exigen) y se reutiliza; los clics muy seguidos no apilan osciladores
+    let audio_context = Rc::new(RefCell::new(None::<web_sys::AudioContext>));
+    let last_click = Rc::new(Cell::new(0.0));
+    let click_sound = move || {
+        let now = js_sys::Date::now();
+        if !sound_enabled.get_untracked() || now - last_click.get() < 60.0 {
+            return;
+        }
+        last_click.set(now);
+        let mut context = audio_context.borrow_mut();
+        if context.is_none() {
+            *context = web_sys::AudioContext::new().ok();
+        }
+        if let Some(context) = context.as_ref() {
+            let _ = play_click(context);
+        }
+    };
+
+    let (touch_start, set_touch_start) = create_signal(None::<(i32, i32)>);
+    let on_touchstart = move |ev: ev::TouchEvent| {
+        let start = ev.touches().get(0).map(|touch| (touch.client_x(), touch.client_y()));
+        set_touch_start.set(start);
+    };
+    let process_swipe = process_input.clone();
+    let on_touchend = move |ev: ev::TouchEvent| {
+        let Some((start_x, start_y)) = touch_start.get_untracked() else {
+            return;
+        };
+        set_touch_start.set(None);
+        if let Some(touch) = ev.changed_touches().get(0) {
+            if is_swipe_left(touch.client_x() - start_x, touch.client_y() - start_y) {
+                log!("* swiped left");
+                process_swipe("<");
+            }
+        }
+    };
+
+    let process_click = process_input.clone();
+    let on_input = Callback::new(move |value: String| {
         log!("* clicked value [{}]", value);
-        
-        let mut calc = calculator_clone.borrow_mut();
-        if let Ok(_) = calc.push(&value) {
-            set_display.set(calc.get_display());
-            set_history.set(calc.get_history());
+        flash_key(&value);
+        click_sound();
+        process_click(&value);
+    });
+    let on_clicked = move |ev: MouseEvent| on_input.call(event_target_value(&ev));
+
+    // Mantener pulsado ⬅ sigue borrando; el clic al soltar no borra uno de más
+    let backspace_timer = store_value(None::<IntervalHandle>);
+    let backspace_repeated = store_value(false);
+    let stop_backspace = move || {
+        if let Some(timer) = backspace_timer.get_value() {
+            timer.clear();
+            backspace_timer.set_value(None);
+        }
+    };
+    let on_backspace_down = move |_| {
+        stop_backspace();
+        backspace_repeated.set_value(false);
+        let timer = set_interval_with_handle(
+            move || {
+                backspace_repeated.set_value(true);
+                on_input.call(String::from("<"));
+            },
+            Duration::from_millis(150),
+        );
+        backspace_timer.set_value(timer.ok());
+    };
+    let on_backspace_click = move |ev: MouseEvent| {
+        if !backspace_repeated.get_value() {
+            on_clicked(ev);
+        }
+        backspace_repeated.set_value(false);
+    };
+    on_cleanup(stop_backspace);
+
+    let on_paste = move |ev: ev::Event| {
+        let Some(text) = ev
+            .dyn_ref::<ClipboardEvent>()
+            .and_then(|ev| ev.clipboard_data())
+            .and_then(|data| data.get_data("text").ok())
+        else {
+            return;
+        };
+        ev.prevent_default();
+        log!("* pasted expression [{}]", text);
+
+        let outcome = calculator.try_update(|calc| calc.evaluate_expression(&text).map(|_| ()));
+        report(outcome.unwrap_or(Ok(())));
+    };
+
+    let on_copy = move |ev: MouseEvent| {
+        // No cuenta como pulsar la pantalla
+        ev.stop_propagation();
+        let text = display.get_untracked();
+        spawn_local(async move {
+            if copy_to_clipboard(&text).await.is_ok() {
+                set_copied.set(true);
+                set_timeout(move || set_copied.set(false), Duration::from_millis(1500));
+            }
+        });
+    };
+
+    // El navegador puede pedir permiso para leer el portapapeles
+    let on_paste_button = move |ev: MouseEvent| {
+        ev.stop_propagation();
+        spawn_local(async move {
+            match read_clipboard().await {
+                Ok(text) => {
+                    let outcome = calculator.try_update(|calc| calc.paste_number(&text));
+                    report(outcome.unwrap_or(Ok(())));
+                }
+                Err(_) => set_error.set(String::from("No se pudo leer el portapapeles")),
+            }
+        });
+    };
+
+    // La distribución de programador trabaja solo con enteros
+    let on_toggle_layout = move |_| {
+        let next = layout.get_untracked().next();
+        calculator.update(|calc| calc.set_integer_mode(next == Layout::Programmer));
+        set_layout.set(next);
+        set_error.set(String::new());
+    };
+
+    let on_toggle_theme = move |_| {
+        let next = if theme.get_untracked() == "dark" { "light" } else { "dark" };
+        set_theme.set(next.to_string());
+    };
+
+    let on_export = move |_| {
+        let json = calculator.with_untracked(Calculator::to_json);
+        if download_file("calculadora.json", "application/json", &json).is_err() {
+            set_error.set(String::from("No se pudo exportar la sesión"));
+        }
+    };
+
+    let on_export_csv = move |_| {
+        let csv = calculator.with_untracked(Calculator::history_to_csv);
+        if download_file("historial.csv", "text/csv", &csv).is_err() {
+            set_error.set(String::from("No se pudo exportar el historial"));
+        }
+    };
+
+    let on_import = move |ev: ev::Event| {
+        let input = event_target::<web_sys::HtmlInputElement>(&ev);
+        let Some(file) = input.files().and_then(|files| files.get(0)) else {
+            return;
+        };
+        // Permite volver a elegir el mismo fichero
+        input.set_value("");
+        spawn_local(async move {
+            let session = read_file(file)
+                .await
+                .map_err(|_| String::from("No se pudo leer el fichero"))
+                .and_then(|json| Calculator::from_json(&json));
+            match session {
+                Ok(calc) => {
+                    calculator.set(calc);
+                    set_error.set(String::new());
+                }
+                Err(msg) => set_error.set(msg),
+            }
+        });
+    };
+
+    let on_tax_rate = move |ev: ev::Event| {
+        if let Ok(rate) = event_target_value(&ev).parse() {
+            calculator.update(|calc| calc.set_tax_rate(rate));
+            set_tax_rate.set(rate);
+        }
+    };
+
+    let on_discount_rate = move |ev: ev::Event| {
+        if let Ok(rate) = event_target_value(&ev).parse() {
+            calculator.update(|calc| calc.set_discount_rate(rate));
+            set_discount_rate.set(rate);
+        }
+    };
+
+    let on_random_min = move |ev: ev::Event| {
+        if let Ok(min) = event_target_value(&ev).parse() {
+            calculator.update(|calc| calc.set_random_range(min, random_max.get_untracked()));
+            set_random_min.set(min);
+        }
+    };
+
+    let on_random_max = move |ev: ev::Event| {
+        if let Ok(max) = event_target_value(&ev).parse() {
+            calculator.update(|calc| calc.set_random_range(random_min.get_untracked(), max));
+            set_random_max.set(max);
+        }
+    };
+
+    let on_min_exponent = move |ev: ev::Event| {
+        if let Ok(min) = event_target_value(&ev).parse() {
+            calculator.update(|calc| calc.set_exponent_range(min, max_exponent.get_untracked()));
+            set_min_exponent.set(min);
+        }
+    };
+
+    let on_max_exponent = move |ev: ev::Event| {
+        if let Ok(max) = event_target_value(&ev).parse() {
+            calculator.update(|calc| calc.set_exponent_range(min_exponent.get_untracked(), max));
+            set_max_exponent.set(max);
+        }
+    };
+
+    let on_decimal_separator = move |ev: ev::Event| {
+        let separator = if event_target_value(&ev) == "," { ',' } else { '.' };
+        calculator.update(|calc| calc.set_decimal_separator(separator));
+        set_decimal_separator.set(separator);
+    };
+
+    let on_max_history = move |ev: ev::Event| {
+        if let Ok(max_history) = event_target_value(&ev).parse() {
+            calculator.update(|calc| calc.set_max_history(max_history));
+            set_max_history.set(max_history);
+        }
+    };
+
+    let on_toggle_auto_clear = move |_| {
+        let enabled = !auto_clear_on_error.get_untracked();
+        calculator.update(|calc| calc.set_auto_clear_on_error(enabled));
+        set_auto_clear_on_error.set(enabled);
+    };
+
+    let on_toggle_currency = move |_| {
+        let enabled = !currency_mode.get_untracked();
+        calculator.update(|calc| calc.set_currency_mode(enabled));
+        set_currency_mode.set(enabled);
+    };
+
+    let on_currency_symbol = move |ev: ev::Event| {
+        let symbol = event_target_value(&ev);
+        calculator.update(|calc| calc.set_currency_symbol(&symbol));
+        set_currency_symbol.set(symbol);
+    };
+
+    let on_precision = move |ev: ev::Event| {
+        if let Ok(precision) = event_target_value(&ev).parse() {
+            calculator.update(|calc| calc.set_precision(precision));
+            set_precision.set(precision);
         }
     };
 
+    let on_max_magnitude = move |ev: ev::Event| {
+        let max_magnitude = event_target_value(&ev).parse().ok();
+        calculator.update(|calc| calc.set_max_magnitude(max_magnitude));
+        set_max_magnitude.set(max_magnitude);
+    };
+
+    let on_rounding_mode = move |ev: ev::Event| {
+        let mode = match event_target_value(&ev).as_str() {
+            "half_even" => RoundingMode::HalfEven,
+            "floor" => RoundingMode::Floor,
+            "ceil" => RoundingMode::Ceil,
+            "truncate" => RoundingMode::Truncate,
+            _ => RoundingMode::HalfUp,
+        };
+        calculator.update(|calc| calc.set_rounding_mode(mode));
+        set_rounding_mode.set(mode);
+    };
+
+    // El rollo se desplaza solo hasta la última línea, ya pintada
+    let tape_ref = create_node_ref::<html::Div>();
+    create_effect(move |_| {
+        tape.track();
+        request_animation_frame(move || {
+            if let Some(tape) = tape_ref.get_untracked() {
+                tape.set_scroll_top(tape.scroll_height());
+            }
+        });
+    });
+
+    // Entrada por teclado; se ignoran los atajos con modificadores del navegador
+    let display_ref = create_node_ref::<html::Div>();
+    let keydown_handle = window_event_listener(ev::keydown, move |ev| {
+        if ev.ctrl_key() || ev.meta_key() || ev.alt_key() {
+            return;
+        }
+        // Lo que se escribe en los campos de ajustes no es para la calculadora
+        let editing = ev
+            .target()
+            .and_then(|target| target.dyn_into::<web_sys::Element>().ok())
+            .is_some_and(|element| matches!(element.tag_name().as_str(), "INPUT" | "SELECT"));
+        if editing {
+            return;
+        }
+        let key = ev.key();
+        if let Some(command) = key_to_command(&key) {
+            ev.prevent_default();
+            log!("* pressed key [{}]", key);
+            flash_key(command);
+            process_input(command);
+            // Escape además devuelve el foco a la pantalla
+            if command == "ac" {
+                if let Some(display) = display_ref.get_untracked() {
+                    let _ = display.focus();
+                }
+            }
+        }
+    });
+    on_cleanup(move || keydown_handle.remove());
+
     view! {
-        <div class="calculator">
-            <div class="display">
-                {move || display.get()}
+        <div class=move || format!("calculator {}", theme.get()) role="application" aria-label="calculadora">
+            // Lo que hace inválida la expresión se marca en rojo, hasta el siguiente espacio
+            <div class="expression">
+                {move || {
+                    let text: Vec<char> = expression.get().chars().collect();
+                    let Some((position, message)) = expression_error.get() else {
+                        return view! { <span>{text.into_iter().collect::<String>()}</span> }.into_view();
+                    };
+                    let position = position.min(text.len());
+                    let end = text[position..].iter().position(|&c| c == ' ').map_or(text.len(), |len| position + len);
+                    view! {
+                        <span>{text[..position].iter().collect::<String>()}</span>
+                        <span class="syntax-error" title=message>{text[position..end].iter().collect::<String>()}</span>
+                        <span>{text[end..].iter().collect::<String>()}</span>
+                    }
+                    .into_view()
+                }}
+            </div>
+            <div
+                class="screen"
+                node_ref=display_ref
+                class:previewing=move || preview.get().is_some()
+                tabindex="0"
+                on:paste=on_paste
+                on:touchstart=on_touchstart
+                on:touchend=on_touchend
+                on:click=move |_| set_show_full.update(|full| *full = !*full)
+                class:full-precision=move || show_full.get() && full_precision.get().is_some()
+            >
+                <Display value=Signal::derive(shown_display)>
+                    <span class="memory-indicator">{move || if memory.get() { "M" } else { "" }}</span>
+                    <span class="memory-indicator">{move || if constant.get() { "K" } else { "" }}</span>
+                    <span class="limit-indicator">{move || if input_limited.get() { "límite" } else { "" }}</span>
+                    <button class="copy" on:click=on_copy aria-label="copiar resultado">
+                        {move || if copied.get() { "Copiado" } else { "Copiar" }}
+                    </button>
+                    <button class="copy" on:click=on_paste_button aria-label="pegar número">"Pegar"</button>
+                </Display>
             </div>
     
-            <div class="keypad">
-                // Botones de la calculadora
-                <button on:click=on_clicked.clone() value="7">"7"</button>
-                <button on:click=on_clicked.clone() value="8">"8"</button>
-                <button on:click=on_clicked.clone() value="9">"9"</button>
-                <button on:click=on_clicked.clone() value="/">"÷"</button>
+            <div class="grand-total">
+                "GT: " {move || grand_total.get()}
+                <span class="data-count">{move || match data_count.get() {
+                    0 => String::new(),
+                    count => format!("n = {}", count),
+                }}</span>
+            </div>
     
-                <button on:click=on_clicked.clone() value="4">"4"</button>
-                <button on:click=on_clicked.clone() value="5">"5"</button>
-                <button on:click=on_clicked.clone() value="6">"6"</button>
-                <button on:click=on_clicked.clone() value="*">"×"</button>
+            <Show when=move || !error.get().is_empty()>
+                <div class="error">
+                    {move || error.get()}
+                    <button on:click=move |_| set_error.set(String::new()) aria-label="cerrar aviso">"✕"</button>
+                </div>
+            </Show>
     
-                <button on:click=on_clicked.clone() value="1">"1"</button>
-                <button on:click=on_clicked.clone() value="2">"2"</button>
-                <button on:click=on_clicked.clone() value="3">"3"</button>
-                <button on:click=on_clicked.clone() value="-">"-"</button>
+            <Show when=move || layout.get() == Layout::Quick>
+                <div class="running-total" aria-live="polite">"Total: " {move || running_total.get()}</div>
+            </Show>
     
-                <button on:click=on_clicked.clone() value="0">"0"</button>
-                <button on:click=on_clicked.clone() value=".">"."</button>
-                <button on:click=on_clicked.clone() value="=">"="</button>
-                <button on:click=on_clicked.clone() value="+">"+"</button>
-            </div>
+            // El modo rápido sustituye al teclado normal
+            <Show when=move || layout.get() != Layout::Quick>
+                <Keypad
+                    on_input=on_input
+                    pressed=last_pressed
+                    decimal_separator=decimal_separator
+                    equals_disabled=divides_by_zero
+                />
+            </Show>
+
+            <Show when=move || layout.get() == Layout::Scientific>
+                {scientific_keypad(on_clicked, last_pressed)}
+            </Show>
+
+            <Show when=move || layout.get() == Layout::Programmer>
+                {programmer_keypad(on_clicked, base, last_pressed)}
+            </Show>
+
+            <Show when=move || layout.get() == Layout::Quick>
+                {quick_keypad(on_clicked, decimal_separator, last_pressed)}
+            </Show>
     
             <div class="control-buttons">
-                <button on:click=on_clicked.clone() value="ac" class="clear">"AC"</button>
-                <button on:click=on_clicked.clone() value="<" class="backspace">"⬅"</button>
+                <button on:click=on_clicked value="ac" class:pressed=move || last_pressed.get() == "ac" aria-label="borrar todo" class="clear">"AC"</button>
+                <button on:click=on_clicked value="ce" class:pressed=move || last_pressed.get() == "ce" aria-label="borrar entrada" class="clear">"CE"</button>
+                <button
+                    on:click=on_backspace_click
+                    on:mousedown=on_backspace_down
+                    on:mouseup=move |_| stop_backspace()
+                    on:mouseleave=move |_| {
+                        // Al soltar fuera del botón no llega ningún clic
+                        stop_backspace();
+                        backspace_repeated.set_value(false);
+                    }
+                    value="<"
+                    class:pressed=move || last_pressed.get() == "<"
+                    aria-label="borrar último dígito"
+                    class="backspace"
+                >
+                    "⬅"
+                </button>
+                <button on:click=on_clicked value="undo" class:pressed=move || last_pressed.get() == "undo" aria-label="deshacer">"↶"</button>
+                <button on:click=on_clicked value="redo" class:pressed=move || last_pressed.get() == "redo" aria-label="rehacer">"↷"</button>
+                <button on:click=on_clicked value="swap" class:pressed=move || last_pressed.get() == "swap" aria-label="intercambiar operandos">"⇄"</button>
+            </div>
+    
+            <button
+                class="settings-toggle"
+                on:click=move |_| set_settings_open.update(|open| *open = !*open)
+                aria-label="ajustes"
+                aria-expanded=move || settings_open.get().to_string()
+            >
+                "⚙"
+            </button>
+
+            <button
+                class="tip-toggle"
+                on:click=move |_| set_tip_open.update(|open| *open = !*open)
+                aria-label="calcular propina"
+                aria-expanded=move || tip_open.get().to_string()
+            >
+                "Propina"
+            </button>
+
+            <Show when=move || tip_open.get()>
+                <div class="tip">
+                    <label>
+                        "Cuenta "
+                        <input type="number" min="0" step="0.01" prop:value=move || tip_bill.get().to_string() on:input=move |ev| set_tip_bill.set(event_target_value(&ev).parse().unwrap_or(0.0)) aria-label="importe de la cuenta"/>
+                    </label>
+                    <label>
+                        " Propina % "
+                        <input type="number" min="0" step="1" prop:value=move || tip_percent.get().to_string() on:input=move |ev| set_tip_percent.set(event_target_value(&ev).parse().unwrap_or(0.0)) aria-label="porcentaje de propina"/>
+                    </label>
+                    <label>
+                        " Personas "
+                        <input type="number" min="1" step="1" prop:value=move || tip_people.get().to_string() on:input=move |ev| set_tip_people.set(event_target_value(&ev).parse().unwrap_or(0)) aria-label="número de personas"/>
+                    </label>
+                    <div class="tip-result" aria-live="polite">"Por persona: " {tip_per_person}</div>
+                </div>
+            </Show>
+
+            <div class="settings" class:open=move || settings_open.get()>
+                <label>
+                    "Decimales "
+                    <select on:change=on_precision prop:value=move || precision.get().to_string() aria-label="decimales">
+                        <option value="2">"2"</option>
+                        <option value="4">"4"</option>
+                        <option value="6">"6"</option>
+                        <option value="8">"8"</option>
+                        <option value="10">"10"</option>
+                    </select>
+                </label>
+                <label>
+                    " Redondeo "
+                    <select
+                        on:change=on_rounding_mode
+                        prop:value=move || match rounding_mode.get() {
+                            RoundingMode::HalfUp => "half_up",
+                            RoundingMode::HalfEven => "half_even",
+                            RoundingMode::Floor => "floor",
+                            RoundingMode::Ceil => "ceil",
+                            RoundingMode::Truncate => "truncate",
+                        }
+                        aria-label="modo de redondeo"
+                    >
+                        <option value="half_up">"Mitad hacia arriba"</option>
+                        <option value="half_even">"Mitad al par"</option>
+                        <option value="floor">"Hacia abajo"</option>
+                        <option value="ceil">"Hacia arriba"</option>
+                        <option value="truncate">"Truncar"</option>
+                    </select>
+                </label>
+                <label>
+                    " Máximo "
+                    <select
+                        on:change=on_max_magnitude
+                        prop:value=move || max_magnitude.get().map_or_else(|| String::from("off"), |max| max.to_string())
+                        aria-label="resultado máximo"
+                    >
+                        <option value="off">"Sin límite"</option>
+                        <option value="1000">"1.000"</option>
+                        <option value="1000000">"1.000.000"</option>
+                        <option value="1000000000">"1.000.000.000"</option>
+                    </select>
+                </label>
+                <label>
+                    " Copiar "
+                    <select
+                        on:change=move |ev| set_copy_mode.set(if event_target_value(&ev) == "result" { CopyMode::ResultOnly } else { CopyMode::FullExpression })
+                        prop:value=move || if copy_mode.get() == CopyMode::ResultOnly { "result" } else { "full" }
+                        aria-label="qué copiar del historial"
+                    >
+                        <option value="full">"Expresión completa"</option>
+                        <option value="result">"Solo el resultado"</option>
+                    </select>
+                </label>
+                <label>
+                    " Historial "
+                    <select on:change=on_max_history prop:value=move || max_history.get().to_string() aria-label="tamaño del historial">
+                        <option value="20">"20"</option>
+                        <option value="50">"50"</option>
+                        <option value="100">"100"</option>
+                    </select>
+                </label>
+                <label>
+                    " Separador "
+                    <select on:change=on_decimal_separator prop:value=move || decimal_separator.get().to_string() aria-label="separador decimal">
+                        <option value=".">"."</option>
+                        <option value=",">","</option>
+                    </select>
+                </label>
+                <label>
+                    " IVA % "
+                    <input type="number" class="tax-rate" prop:value=move || tax_rate.get().to_string() min="0" step="0.5" on:change=on_tax_rate aria-label="porcentaje de impuesto"/>
+                </label>
+                <label>
+                    " Dto % "
+                    <input type="number" class="discount-rate" prop:value=move || discount_rate.get().to_string() min="0" max="100" step="1" on:change=on_discount_rate aria-label="porcentaje de descuento"/>
+                </label>
+                <label>
+                    " Moneda "
+                    <input type="text" class="currency-symbol" prop:value=move || currency_symbol.get() maxlength="3" on:change=on_currency_symbol aria-label="símbolo de moneda"/>
+                </label>
+                <label>
+                    " Aleatorio "
+                    <input type="number" class="random-range" prop:value=move || random_min.get().to_string() on:change=on_random_min aria-label="mínimo aleatorio"/>
+                    " a "
+                    <input type="number" class="random-range" prop:value=move || random_max.get().to_string() on:change=on_random_max aria-label="máximo aleatorio"/>
+                </label>
+                <label>
+                    " Científica por debajo de 1e"
+                    <input type="number" class="exponent-range" prop:value=move || min_exponent.get().to_string() max="0" on:change=on_min_exponent aria-label="exponente mínimo"/>
+                    " y desde 1e"
+                    <input type="number" class="exponent-range" prop:value=move || max_exponent.get().to_string() min="1" on:change=on_max_exponent aria-label="exponente máximo"/>
+                </label>
+                <button on:click=on_clicked value="angle" class:pressed=move || last_pressed.get() == "angle" aria-label="cambiar unidad de ángulo" class="angle-toggle">
+                    {move || if angle_mode.get() == AngleMode::Degrees { "DEG" } else { "RAD" }}
+                </button>
+                <button class="layout-toggle" on:click=on_toggle_layout aria-label="cambiar distribución">
+                    {move || layout.get().next().label()}
+                </button>
+                <button class="sound-toggle" on:click=move |_| set_sound_enabled.update(|enabled| *enabled = !*enabled) aria-label="sonido de las teclas">
+                    {move || if sound_enabled.get() { "Sonido: sí" } else { "Sonido: no" }}
+                </button>
+                <button class="auto-clear-toggle" on:click=on_toggle_auto_clear aria-label="borrar tras un error">
+                    {move || if auto_clear_on_error.get() { "Borrar tras error: sí" } else { "Borrar tras error: no" }}
+                </button>
+                <button class="currency-toggle" on:click=on_toggle_currency aria-label="modo moneda">
+                    {move || if currency_mode.get() { "Moneda: sí" } else { "Moneda: no" }}
+                </button>
+                <button class="theme-toggle" on:click=on_toggle_theme aria-label="cambiar tema">
+                    {move || if theme.get() == "dark" { "Tema claro" } else { "Tema oscuro" }}
+                </button>
+                <div class="session">
+                    <button on:click=on_export aria-label="exportar sesión">"Exportar"</button>
+                    <label class="import">
+                        "Importar"
+                        <input type="file" accept="application/json,.json" on:change=on_import aria-label="importar sesión"/>
+                    </label>
+                </div>
             </div>
     
+            <div class="tape" node_ref=tape_ref aria-label="rollo de papel">
+                <button on:click=on_clicked value="tape_clear" class:pressed=move || last_pressed.get() == "tape_clear" aria-label="borrar rollo" class="clear-tape">"Borrar rollo"</button>
+                {move || tape.get().into_iter().map(|line| view! { <div class="tape-line">{line}</div> }).collect::<Vec<_>>()}
+            </div>
+
             <div class="history">
                 <h3>"Historial"</h3>
+                <button on:click=on_clicked value="clear_history" class:pressed=move || last_pressed.get() == "clear_history" aria-label="borrar historial" class="clear-history">"Borrar historial"</button>
+                <button on:click=on_export_csv class="export-csv" aria-label="exportar historial en CSV">"Exportar CSV"</button>
                 {move || {
-                    let calculator_ref = calculator.clone();  // Clonamos calculator fuera del mapa
-                    history.get().into_iter().map(move |operation| {
-                        let calculator_clone = calculator_ref.clone();  // Clonamos dentro del mapa para evitar mover `calculator`
+                    history.get().into_iter().enumerate().map(move |(index, entry)| {
+                        let entry_copy = entry.clone();
+                        let entry_latex = entry.to_latex();
                         view! {
-                            <div class="history-item">
-                                {operation.clone()}
-                                <button 
+                            <div
+                                class="history-item"
+                                on:mouseenter=move |_| {
+                                    set_preview.set(Some(calculator.with_untracked(|calc| calc.format_value(entry.result))));
+                                }
+                                on:mouseleave=move |_| set_preview.set(None)
+                            >
+                                <span class="time">{format_timestamp(entry.timestamp)}</span>
+                                {entry.to_string()}
+                                <button
+                                    aria-label="usar resultado"
                                     on:click=move |_| {
-                                        let result = operation.split("=").last()
-                                            .unwrap_or("0")
-                                            .trim()
-                                            .to_string();
-                                        let mut calc = calculator_clone.borrow_mut();
-                                        calc.current_number = result;
-                                        calc.update_display();
-                                        set_display.set(calc.get_display());
+                                        calculator.update(|calc| calc.load_value(entry.result));
                                     }
                                 >
                                     "Usar"
                                 </button>
+                                <button
+                                    aria-label="insertar resultado"
+                                    on:click=move |_| {
+                                        let outcome = calculator.try_update(|calc| calc.insert_operand(entry.result));
+                                        report(outcome.unwrap_or(Ok(())));
+                                    }
+                                >
+                                    "Insertar"
+                                </button>
+                                <button
+                                    aria-label="re-ejecutar expresión"
+                                    on:click=move |_| {
+                                        let outcome = calculator.try_update(|calc| calc.rerun(index));
+                                        report(outcome.unwrap_or(Ok(())));
+                                    }
+                                >
+                                    "Re-ejecutar"
+                                </button>
+                                <button
+                                    class="copy"
+                                    aria-label="copiar entrada"
+                                    on:click=move |_| {
+                                        let text = entry_copy.copy_text(copy_mode.get_untracked());
+                                        spawn_local(async move {
+                                            if copy_to_clipboard(&text).await.is_ok() {
+                                                set_copied_entry.set(Some(index));
+                                                set_timeout(
+                                                    move || {
+                                                        if copied_entry.get_untracked() == Some(index) {
+                                                            set_copied_entry.set(None);
+                                                        }
+                                                    },
+                                                    Duration::from_millis(1500),
+                                                );
+                                            }
+                                        });
+                                    }
+                                >
+                                    {move || if copied_entry.get() == Some(index) { "Copiado" } else { "Copiar" }}
+                                </button>
+                                <button
+                                    class="copy"
+                                    aria-label="copiar como LaTeX"
+                                    on:click=move |_| {
+                                        let latex = entry_latex.clone();
+                                        spawn_local(async move {
+                                            if copy_to_clipboard(&latex).await.is_ok() {
+                                                set_copied_latex.set(Some(index));
+                                                set_timeout(
+                                                    move || {
+                                                        if copied_latex.get_untracked() == Some(index) {
+                                                            set_copied_latex.set(None);
+                                                        }
+                                                    },
+                                                    Duration::from_millis(1500),
+                                                );
+                                            }
+                                        });
+                                    }
+                                >
+                                    {move || if copied_latex.get() == Some(index) { "Copiado" } else { "LaTeX" }}
+                                </button>
+                                <button
+                                    class="remove"
+                                    aria-label="eliminar entrada"
+                                    on:click=move |_| {
+                                        calculator.update(|calc| calc.remove_history(index));
+                                        // La fila desaparece sin llegar a recibir `mouseleave`
+                                        set_preview.set(None);
+                                    }
+                                >
+                                    "✕"
+                                </button>
                             </div>
                         }
                     }).collect::<Vec<_>>()
                 }}
+                <div class="history-sum">"Suma: " {move || history_sum.get()}</div>
+                <div class="stats">
+                    {move || {
+                        let (operations, entries) = stats.get();
+                        format!("Operaciones: {} · En el historial: {}", operations, entries)
+                    }}
+                </div>
             </div>
         </div>
     }
@@ -189,3 +1364,64 @@ fn App() -> impl IntoView {
 fn main() {
     mount_to_body(|| view! { <App/> });
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_keys_to_commands() {
+        assert_eq!(key_to_command("Delete"), Some("ce"));
+        assert_eq!(key_to_command("c"), Some("ce"));
+        assert_eq!(key_to_command("7"), Some("7"));
+        assert_eq!(key_to_command("Enter"), Some("="));
+        assert_eq!(key_to_command(","), Some("."));
+        assert_eq!(key_to_command("Backspace"), Some("<"));
+        assert_eq!(key_to_command("Escape"), Some("ac"));
+        assert_eq!(key_to_command("a"), None);
+    }
+
+    #[test]
+    fn every_mapped_command_is_a_calculator_input() {
+        for (key, command) in KEY_MAP {
+            let mut calc = Calculator::new();
+            let setup: &[&str] = if *command == ")" { &["(", "1"] } else { &["1"] };
+            calc.push_all(setup).unwrap();
+            assert_eq!(calc.push(command), Ok(()), "tecla {}", key);
+            // Una orden desconocida acabaría escrita en el número
+            assert!(!calc.get_display().contains(char::is_alphabetic), "tecla {}", key);
+        }
+    }
+
+    #[test]
+    fn detects_left_swipes() {
+        assert!(is_swipe_left(-80, 10));
+        assert!(!is_swipe_left(-30, 0));
+        assert!(!is_swipe_left(80, 0));
+        assert!(!is_swipe_left(-60, -90));
+    }
+
+    #[test]
+    fn moves_keypad_focus_along_the_grid() {
+        // Fila de cuatro, fila con un botón ancho al final y fila de dos anchos
+        let spans = [1, 1, 1, 1, 1, 1, 2, 2, 2];
+        assert_eq!(keypad_neighbor(&spans, 1, "ArrowDown"), 5);
+        assert_eq!(keypad_neighbor(&spans, 2, "ArrowDown"), 6);
+        assert_eq!(keypad_neighbor(&spans, 3, "ArrowDown"), 6);
+        assert_eq!(keypad_neighbor(&spans, 6, "ArrowDown"), 8);
+        assert_eq!(keypad_neighbor(&spans, 8, "ArrowUp"), 6);
+        assert_eq!(keypad_neighbor(&spans, 7, "ArrowUp"), 4);
+        assert_eq!(keypad_neighbor(&spans, 8, "ArrowDown"), 8);
+        assert_eq!(keypad_neighbor(&spans, 0, "ArrowLeft"), 0);
+        assert_eq!(keypad_neighbor(&spans, 8, "ArrowRight"), 8);
+        assert_eq!(keypad_neighbor(&spans, 4, "ArrowRight"), 5);
+    }
+
+    #[test]
+    fn shrinks_the_display_font_for_long_numbers() {
+        assert_eq!(display_font_size(1), "2em");
+        assert_eq!(display_font_size(12), "1.5em");
+        assert_eq!(display_font_size(20), "1.2em");
+        assert_eq!(display_font_size(30), "0.9em");
+    }
+}